@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+// FF Logs serves ability and status icons from here, by the file name in masterData.
+const ICON_BASE_URL: &str = "https://assets.rpglogs.com/img/ff/abilities/";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbilityInfo {
+    pub name: String,
+    pub icon: String,
+    // FF Logs' damage school/ability type code, as a string
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+/// Names and icons of every ability and status that shows up in a report, by game ID.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AbilityTable(HashMap<i64, AbilityInfo>);
+impl AbilityTable {
+    pub fn insert(&mut self, game_id: i64, info: AbilityInfo) {
+        self.0.insert(game_id, info);
+    }
+
+    pub fn get(&self, game_id: i64) -> Option<&AbilityInfo> {
+        self.0.get(&game_id)
+    }
+
+    /// The ability's name, or its ID if the report doesn't know about it.
+    pub fn name(&self, game_id: i64) -> String {
+        match self.get(game_id) {
+            Some(info) if !info.name.is_empty() => info.name.clone(),
+            _ => format!("#{}", game_id),
+        }
+    }
+
+    pub fn icon_url(&self, game_id: i64) -> Option<String> {
+        self.get(game_id)
+            .filter(|info| !info.icon.is_empty())
+            .map(|info| format!("{}{}", ICON_BASE_URL, info.icon))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AbilityInfo, AbilityTable};
+
+    #[test]
+    fn ability_table_test() {
+        let mut table = AbilityTable::default();
+        table.insert(
+            31000,
+            AbilityInfo {
+                name: "Gaoler's Flail".to_string(),
+                icon: "000000-000405.png".to_string(),
+                type_: "1024".to_string(),
+            },
+        );
+        table.insert(
+            1002000,
+            AbilityInfo {
+                name: String::new(),
+                icon: String::new(),
+                type_: "0".to_string(),
+            },
+        );
+
+        assert_eq!(table.name(31000), "Gaoler's Flail");
+        assert_eq!(table.name(1002000), "#1002000");
+        assert_eq!(table.name(7), "#7");
+        assert_eq!(
+            table.icon_url(31000).as_deref(),
+            Some("https://assets.rpglogs.com/img/ff/abilities/000000-000405.png")
+        );
+        assert_eq!(table.icon_url(1002000), None);
+    }
+}
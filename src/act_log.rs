@@ -0,0 +1,970 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    f64::consts::PI,
+    path::Path,
+};
+
+use crate::{
+    abilities::{AbilityInfo, AbilityTable},
+    events::{Event, Resources, SourceInfo, TargetInfo},
+    positions::{Position, Rect},
+    report::{build_position_histories, FightSummary, Pull},
+    ActorInfo,
+};
+
+// The id ACT uses for "nobody", e.g. the source of fall damage
+const NO_ACTOR: &str = "E0000000";
+// FF Logs offsets status IDs so they don't collide with abilities
+const STATUS_ID_OFFSET: i64 = 1_000_000;
+
+// Space left around the players' positions, and the smallest arena drawn, in FF Logs units
+// (hundredths of a yalm), so a pull where nobody moves still gets an arena to draw in
+const ARENA_MARGIN: f64 = 500.0;
+const MIN_ARENA_SIZE: f64 = 4000.0;
+
+// Director commands (line 33) that start and end an encounter
+const DIRECTOR_COMMENCE: [i64; 2] = [0x40000001, 0x40000006];
+const DIRECTOR_VICTORY: [i64; 1] = [0x40000003];
+const DIRECTOR_WIPE: [i64; 2] = [0x40000005, 0x40000010];
+
+// Low byte of an ability's first effect
+const EFFECT_DAMAGE: i64 = 0x03;
+const EFFECT_HEAL: i64 = 0x04;
+const EFFECT_BLOCKED: i64 = 0x05;
+const EFFECT_PARRIED: i64 = 0x06;
+const FLAG_CRIT: i64 = 0x2000;
+const FLAG_DIRECT_HIT: i64 = 0x4000;
+
+/// Reads an ACT network log (as written by the FFXIV ACT plugin) into pulls, so they can be
+/// rendered without FF Logs. Pulls are numbered from 1 in the order they happened.
+pub fn load(path: &Path) -> Result<Vec<Pull>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    let code = path.file_stem().map_or_else(
+        || "act".to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+
+    let log = parse(&code, &text);
+    if let Some(first) = log.skipped.first() {
+        println!(
+            "Skipped {} lines of {} that couldn't be read, starting with {}",
+            log.skipped.len(),
+            path.display(),
+            first
+        );
+    }
+    if log.pulls.is_empty() {
+        return Err(format!("{} has no pulls in it", path.display()).into());
+    }
+    println!(
+        "Loaded {} pulls and {} actors from {}",
+        log.pulls.len(),
+        log.pulls
+            .iter()
+            .flat_map(|pull| pull.actors.keys())
+            .collect::<HashSet<_>>()
+            .len(),
+        path.display()
+    );
+    Ok(log.pulls)
+}
+
+/// The pulls read from a log, and a description of every line that had to be skipped.
+pub struct ActLog {
+    pub pulls: Vec<Pull>,
+    pub skipped: Vec<String>,
+}
+
+// A zone change or a director line ends one pull and starts the next. Without any director lines
+// (e.g. a log cut down to one encounter) every zone is one pull; with them, only the stretches
+// that commenced are.
+pub fn parse(code: &str, text: &str) -> ActLog {
+    let mut log = LogState::default();
+    let mut skipped = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(e) = log.read_line(line) {
+            skipped.push(format!("line {}: {}", i + 1, e));
+        }
+    }
+    log.end_segment(None);
+
+    let any_commenced = log.segments.iter().any(|segment| segment.commenced);
+    let segments = std::mem::take(&mut log.segments);
+    let pulls = segments
+        .into_iter()
+        .filter(|segment| segment.commenced || !any_commenced)
+        .enumerate()
+        .map(|(i, segment)| log.segment_pull(code, i as i64 + 1, segment))
+        .collect();
+    ActLog { pulls, skipped }
+}
+
+// The lines between two pull boundaries
+#[derive(Default)]
+struct Segment {
+    // Log time of the first line, or of the commence if there was one
+    start: i64,
+    end: i64,
+    commenced: bool,
+    kill: bool,
+    zone: Option<(i64, String)>,
+    // Everyone the segment's lines mention
+    actors: HashSet<i64>,
+    events: Vec<Event>,
+    // Positions from combatant lines, which have no event of their own
+    samples: Vec<(i64, i64, Position)>,
+}
+
+#[derive(Default)]
+struct LogState {
+    // Epoch milliseconds of the first line; event timestamps count from here
+    start: Option<i64>,
+    zone: Option<(i64, String)>,
+    actors: HashMap<i64, ActorInfo>,
+    abilities: AbilityTable,
+    last_positions: HashMap<i64, (f64, f64)>,
+    segment: Option<Segment>,
+    segments: Vec<Segment>,
+}
+impl LogState {
+    fn read_line(&mut self, line: &str) -> Result<(), String> {
+        let fields = line.split('|').collect::<Vec<_>>();
+        if fields.len() < 3 {
+            return Ok(());
+        }
+
+        let time =
+            parse_timestamp(fields[1]).ok_or_else(|| format!("bad timestamp {:?}", fields[1]))?;
+        let start = *self.start.get_or_insert(time);
+        let timestamp = time - start;
+        let segment = self.segment(timestamp);
+        segment.end = segment.end.max(timestamp);
+
+        match fields[0] {
+            "01" => {
+                let zone = (hex(field(&fields, 2)?)?, field(&fields, 3)?.to_string());
+                self.end_segment(Some(timestamp));
+                self.zone = Some(zone);
+            }
+            "33" => self.director(&fields, timestamp)?,
+            "03" => self.add_combatant(&fields, timestamp)?,
+            "20" => self.begin_cast(&fields, timestamp)?,
+            "21" | "22" => self.ability(&fields, timestamp)?,
+            "25" => {
+                let target = self.actor(&fields, 2, 3)?;
+                let source = self.actor(&fields, 4, 5)?;
+                self.push_event(Event::Death {
+                    source: SourceInfo::new(source),
+                    target: TargetInfo::new(target),
+                    source_resources: None,
+                    target_resources: None,
+                    timestamp,
+                });
+            }
+            "26" | "30" => self.status(&fields, timestamp)?,
+            "27" => {
+                let target = self.actor(&fields, 2, 3)?;
+                self.push_event(Event::HeadMarker {
+                    marker_id: Some(hex(field(&fields, 6)?)?),
+                    source: SourceInfo::new(-1),
+                    target: TargetInfo::new(target),
+                    source_resources: None,
+                    target_resources: None,
+                    timestamp,
+                });
+            }
+            "35" => {
+                let source = self.actor(&fields, 2, 3)?;
+                let target = self.actor(&fields, 4, 5)?;
+                self.push_event(Event::Tether {
+                    ability_game_id: hex(field(&fields, 8)?)?,
+                    source: SourceInfo::new(source),
+                    target: TargetInfo::new(target),
+                    timestamp,
+                });
+            }
+            "261" => self.combatant_memory(&fields, timestamp)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // The segment lines are currently going to, started at `timestamp` if there isn't one
+    fn segment(&mut self, timestamp: i64) -> &mut Segment {
+        let zone = &self.zone;
+        self.segment.get_or_insert_with(|| Segment {
+            start: timestamp,
+            end: timestamp,
+            zone: zone.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn current(&mut self) -> &mut Segment {
+        self.segment
+            .as_mut()
+            .expect("lines always start a segment first")
+    }
+
+    fn push_event(&mut self, event: Event) {
+        self.current().events.push(event);
+    }
+
+    // Closes the current segment; the next line starts a new one. Segments with nothing in them
+    // are dropped, unless they're a commenced encounter.
+    fn end_segment(&mut self, end: Option<i64>) {
+        if let Some(mut segment) = self.segment.take() {
+            if let Some(end) = end {
+                segment.end = end;
+            }
+            if segment.commenced || !segment.events.is_empty() || !segment.samples.is_empty() {
+                self.segments.push(segment);
+            }
+        }
+    }
+
+    // Line 33: the instance's director announcing the start or the end of an encounter
+    fn director(&mut self, fields: &[&str], timestamp: i64) -> Result<(), String> {
+        let command = hex(field(fields, 3)?)?;
+        if DIRECTOR_COMMENCE.contains(&command) {
+            // A second commence without an outcome in between starts over
+            if self.current().commenced {
+                self.end_segment(Some(timestamp));
+            }
+            let segment = self.segment(timestamp);
+            segment.start = timestamp;
+            segment.commenced = true;
+        } else if DIRECTOR_VICTORY.contains(&command) || DIRECTOR_WIPE.contains(&command) {
+            self.current().kill = DIRECTOR_VICTORY.contains(&command);
+            self.end_segment(Some(timestamp));
+        }
+        Ok(())
+    }
+
+    // Records an actor the first time it shows up, and returns its id
+    fn actor(
+        &mut self,
+        fields: &[&str],
+        id_field: usize,
+        name_field: usize,
+    ) -> Result<i64, String> {
+        let id = actor_id(field(fields, id_field)?)?;
+        let name = field(fields, name_field)?;
+        if id != -1 {
+            self.current().actors.insert(id);
+            let actor = self
+                .actors
+                .entry(id)
+                .or_insert_with(|| new_actor(id, name, None, false));
+            // Lines sometimes leave the name out, so take it from whichever line has it first
+            if actor.name.is_empty() {
+                actor.name = name.to_string();
+            }
+        }
+        Ok(id)
+    }
+
+    fn ability_name(&mut self, game_id: i64, name: &str) {
+        if self.abilities.get(game_id).is_none() && !name.is_empty() {
+            self.abilities.insert(
+                game_id,
+                AbilityInfo {
+                    name: name.to_string(),
+                    icon: String::new(),
+                    type_: String::new(),
+                },
+            );
+        }
+    }
+
+    fn move_actor(&mut self, id: i64, timestamp: i64, (x, y): (f64, f64)) {
+        self.last_positions.insert(id, (x, y));
+        let segment = self.current();
+        segment.actors.insert(id);
+        segment.samples.push((
+            id,
+            timestamp,
+            (game_to_logs(x) as f64, game_to_logs(y) as f64),
+        ));
+    }
+
+    fn add_combatant(&mut self, fields: &[&str], timestamp: i64) -> Result<(), String> {
+        let id = actor_id(field(fields, 2)?)?;
+        let job = hex(field(fields, 4)?)?;
+        let owner = actor_id(field(fields, 6)?)?;
+        let owned = owner != -1 && owner != 0;
+        self.actors
+            .insert(id, new_actor(id, field(fields, 3)?, Some(job), owned));
+
+        let x = number(field(fields, 17)?)?;
+        let y = number(field(fields, 18)?)?;
+        self.move_actor(id, timestamp, (x, y));
+        Ok(())
+    }
+
+    // Line 261 carries key/value pairs; "Change" lines only have the keys that changed
+    fn combatant_memory(&mut self, fields: &[&str], timestamp: i64) -> Result<(), String> {
+        let change = field(fields, 2)?;
+        let id = actor_id(field(fields, 3)?)?;
+        let values = fields[4..]
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect::<HashMap<_, _>>();
+
+        if change == "Add" {
+            let job = values
+                .get("Job")
+                .map(|job| job.parse::<i64>())
+                .transpose()
+                .map_err(|e| format!("bad job: {}", e))?;
+            let owned = values
+                .get("OwnerID")
+                .is_some_and(|owner| actor_id(owner).is_ok_and(|owner| owner != -1 && owner != 0));
+            let name = values.get("Name").copied().unwrap_or_default();
+            self.actors.insert(id, new_actor(id, name, job, owned));
+        } else if change != "Change" {
+            return Ok(());
+        }
+
+        let last = self.last_positions.get(&id).copied();
+        let x = values.get("PosX").map(|x| number(x)).transpose()?;
+        let y = values.get("PosY").map(|y| number(y)).transpose()?;
+        if x.is_none() && y.is_none() {
+            return Ok(());
+        }
+        // A change to only one axis needs the other from before
+        if let (Some(x), Some(y)) = (x.or(last.map(|(x, _)| x)), y.or(last.map(|(_, y)| y))) {
+            self.move_actor(id, timestamp, (x, y));
+        }
+        Ok(())
+    }
+
+    fn begin_cast(&mut self, fields: &[&str], timestamp: i64) -> Result<(), String> {
+        let source = self.actor(fields, 2, 3)?;
+        let ability_game_id = hex(field(fields, 4)?)?;
+        self.ability_name(ability_game_id, field(fields, 5)?);
+        let target = self.actor(fields, 6, 7)?;
+        let cast_time: f64 = number(field(fields, 8)?)?;
+
+        self.push_event(Event::BeginCast {
+            ability_game_id,
+            duration: (cast_time * 1000.0).round() as i64,
+            source: SourceInfo::new(source),
+            target: TargetInfo::new(target),
+            timestamp,
+        });
+        Ok(())
+    }
+
+    // Lines 21 and 22 are the same, but 22 is one line per target of an area ability
+    fn ability(&mut self, fields: &[&str], timestamp: i64) -> Result<(), String> {
+        let source = self.actor(fields, 2, 3)?;
+        let ability_game_id = hex(field(fields, 4)?)?;
+        self.ability_name(ability_game_id, field(fields, 5)?);
+        let target = self.actor(fields, 6, 7)?;
+        let flags = hex(field(fields, 8)?)?;
+        let amount = decode_amount(hex(field(fields, 9)?)?);
+        let target_resources = || resources(fields, 24);
+        let source_resources = || resources(fields, 34);
+
+        // Only report the cast once, on the first target
+        let target_index = fields.get(45).filter(|index| !index.is_empty());
+        if target_index.is_none_or(|index| hex(index) == Ok(0)) {
+            self.push_event(Event::Cast {
+                ability_game_id,
+                source: SourceInfo::new(source),
+                target: TargetInfo::new(target),
+                source_resources: source_resources()?,
+                target_resources: target_resources()?,
+                timestamp,
+            });
+        }
+
+        match flags & 0xff {
+            EFFECT_DAMAGE | EFFECT_BLOCKED | EFFECT_PARRIED => self.push_event(Event::Damage {
+                ability_game_id,
+                amount,
+                unmitigated_amount: None,
+                multiplier: None,
+                overkill: None,
+                direct_hit: Some(flags & FLAG_DIRECT_HIT != 0),
+                hit_type: if flags & FLAG_CRIT != 0 { 2 } else { 1 },
+                source: SourceInfo::new(source),
+                target: TargetInfo::new(target),
+                source_resources: source_resources()?,
+                target_resources: target_resources()?,
+                timestamp,
+            }),
+            EFFECT_HEAL => self.push_event(Event::Heal {
+                ability_game_id,
+                amount,
+                multiplier: None,
+                hit_type: 1,
+                source: SourceInfo::new(source),
+                target: TargetInfo::new(target),
+                source_resources: source_resources()?,
+                target_resources: target_resources()?,
+                timestamp,
+            }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Lines 26 (gained) and 30 (lost). The log doesn't say whether a status is a buff or a debuff,
+    // so anything an enemy puts out counts as a debuff.
+    fn status(&mut self, fields: &[&str], timestamp: i64) -> Result<(), String> {
+        let ability_game_id = hex(field(fields, 2)?)? + STATUS_ID_OFFSET;
+        self.ability_name(ability_game_id, field(fields, 3)?);
+        let source = self.actor(fields, 5, 6)?;
+        let target = self.actor(fields, 7, 8)?;
+        let debuff = source == -1
+            || self
+                .actors
+                .get(&source)
+                .is_some_and(|actor| actor.type_ == "NPC");
+        let (source, target) = (SourceInfo::new(source), TargetInfo::new(target));
+
+        let event = if fields[0] == "26" {
+            let duration: f64 = number(field(fields, 4)?)?;
+            let duration = (duration * 1000.0).round() as i64;
+            if debuff {
+                Event::ApplyDebuff {
+                    ability_game_id,
+                    extra_ability_game_id: None,
+                    duration,
+                    source,
+                    target,
+                    timestamp,
+                }
+            } else {
+                Event::ApplyBuff {
+                    ability_game_id,
+                    extra_ability_game_id: None,
+                    duration,
+                    source,
+                    target,
+                    timestamp,
+                }
+            }
+        } else if debuff {
+            Event::RemoveDebuff {
+                ability_game_id,
+                source,
+                target,
+                timestamp,
+            }
+        } else {
+            Event::RemoveBuff {
+                ability_game_id,
+                source,
+                target,
+                timestamp,
+            }
+        };
+        self.push_event(event);
+        Ok(())
+    }
+
+    fn segment_pull(&mut self, code: &str, id: i64, mut segment: Segment) -> Pull {
+        // Lines are written as packets arrive, which isn't always in order
+        segment.events.sort_by_key(Event::get_timestamp);
+
+        let mut positions = build_position_histories(&segment.events);
+        for (id, timestamp, position) in segment.samples {
+            positions
+                .entry(id)
+                .or_default()
+                .add_update(timestamp, position);
+        }
+        // Everything with positions needs an actor, even one the log never named
+        for id in positions.keys() {
+            segment.actors.insert(*id);
+            self.actors
+                .entry(*id)
+                .or_insert_with(|| new_actor(*id, "", None, false));
+        }
+        let actors = segment
+            .actors
+            .iter()
+            .filter_map(|id| Some((*id, self.actors.get(id)?.clone())))
+            .collect::<HashMap<_, _>>();
+
+        // The arena is wherever the players went; enemies can sit outside of it
+        let bounding_box = positions
+            .iter()
+            .filter(|(id, _)| actors.get(id).is_some_and(|a| a.type_ == "Player"))
+            .flat_map(|(_, history)| history.samples())
+            .fold(None, |bounds: Option<(Position, Position)>, (_, (x, y))| {
+                Some(match bounds {
+                    None => ((x, y), (x, y)),
+                    Some(((min_x, min_y), (max_x, max_y))) => {
+                        ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+                    }
+                })
+            })
+            .map_or(((0.0, 0.0), (1.0, 1.0)), pad_arena);
+
+        // Enemies are whichever NPCs the players hit
+        let mut enemies = segment
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Damage { source, target, .. } => Some((source.id, target.id)),
+                _ => None,
+            })
+            .filter(|(source, _)| actors.get(source).is_some_and(|a| a.type_ == "Player"))
+            .filter_map(|(_, target)| actors.get(&target))
+            .filter(|actor| actor.type_ == "NPC")
+            .map(|actor| actor.name.clone())
+            .collect::<Vec<_>>();
+        enemies.sort();
+        enemies.dedup();
+
+        // The log only knows the game's territory ID, so that stands in for the encounter
+        let (encounter_id, name) = segment.zone.unwrap_or_else(|| (0, code.to_string()));
+        Pull {
+            code: code.to_string(),
+            fight: FightSummary {
+                id,
+                encounter_id,
+                name,
+                start_time: segment.start as f64,
+                end_time: segment.end as f64,
+                bounding_box,
+                kill: segment.kill,
+                fight_percentage: None,
+                enemies,
+            },
+            actors,
+            abilities: self.abilities.clone(),
+            events: segment.events,
+            positions,
+        }
+    }
+}
+
+// Widens the box around the players' positions by the margin, to at least the minimum size
+fn pad_arena(((min_x, min_y), (max_x, max_y)): Rect) -> Rect {
+    let pad = |min: f64, max: f64| {
+        let center = (min + max) / 2.0;
+        let half = ((max - min) / 2.0 + ARENA_MARGIN).max(MIN_ARENA_SIZE / 2.0);
+        (center - half, center + half)
+    };
+    let ((min_x, max_x), (min_y, max_y)) = (pad(min_x, max_x), pad(min_y, max_y));
+    ((min_x, min_y), (max_x, max_y))
+}
+
+fn field<'a>(fields: &[&'a str], index: usize) -> Result<&'a str, String> {
+    fields
+        .get(index)
+        .copied()
+        .ok_or_else(|| format!("expected at least {} fields", index + 1))
+}
+
+fn hex(text: &str) -> Result<i64, String> {
+    i64::from_str_radix(text, 16).map_err(|e| format!("bad hex value {:?}: {}", text, e))
+}
+
+fn number(text: &str) -> Result<f64, String> {
+    text.parse()
+        .map_err(|e| format!("bad number {:?}: {}", text, e))
+}
+
+fn actor_id(text: &str) -> Result<i64, String> {
+    if text.is_empty() || text.eq_ignore_ascii_case(NO_ACTOR) {
+        Ok(-1)
+    } else {
+        hex(text)
+    }
+}
+
+// FF Logs positions are hundredths of the game's
+fn game_to_logs(value: f64) -> i64 {
+    (value * 100.0).round() as i64
+}
+
+// Player ids start with 10, everything else in the fight is an NPC (or a pet, if it has an owner)
+fn new_actor(id: i64, name: &str, job: Option<i64>, owned: bool) -> ActorInfo {
+    let (type_, subtype) = if id >> 24 == 0x10 {
+        ("Player", job.and_then(job_name).unwrap_or_default())
+    } else if owned {
+        ("Pet", "Pet")
+    } else {
+        ("NPC", "NPC")
+    };
+    ActorInfo {
+        name: name.to_string(),
+        type_: type_.to_string(),
+        subtype: subtype.to_string(),
+    }
+}
+
+// FF Logs' names for the game's class/job IDs
+fn job_name(job: i64) -> Option<&'static str> {
+    Some(match job {
+        1 | 19 => "Paladin",
+        2 | 20 => "Monk",
+        3 | 21 => "Warrior",
+        4 | 22 => "Dragoon",
+        5 | 23 => "Bard",
+        6 | 24 => "WhiteMage",
+        7 | 25 => "BlackMage",
+        26 | 27 => "Summoner",
+        28 => "Scholar",
+        29 | 30 => "Ninja",
+        31 => "Machinist",
+        32 => "DarkKnight",
+        33 => "Astrologian",
+        34 => "Samurai",
+        35 => "RedMage",
+        36 => "BlueMage",
+        37 => "Gunbreaker",
+        38 => "Dancer",
+        39 => "Reaper",
+        40 => "Sage",
+        41 => "Viper",
+        42 => "Pictomancer",
+        _ => return None,
+    })
+}
+
+// Damage is two bytes, unless the third has 0x40 set, in which case the fourth byte is the top
+// of a 24 bit amount
+fn decode_amount(value: i64) -> i64 {
+    let [a, b, c, d] = (value as u32).to_be_bytes().map(i64::from);
+    if c & 0x40 != 0 {
+        d << 16 | a << 8 | b
+    } else {
+        a << 8 | b
+    }
+}
+
+// HP, MP, TP, position and heading, starting at `first`. Missing when there's no actor.
+fn resources(fields: &[&str], first: usize) -> Result<Option<Resources>, String> {
+    let hp = fields.get(first).copied().unwrap_or_default();
+    if hp.is_empty() {
+        return Ok(None);
+    }
+    let int = |index: usize| -> Result<i64, String> {
+        let text = field(fields, first + index)?;
+        text.parse()
+            .map_err(|e| format!("bad number {:?}: {}", text, e))
+    };
+
+    let heading = number(field(fields, first + 9)?)?;
+    Ok(Some(Resources {
+        absorb: None,
+        facing: (heading / PI * 1000.0).round() as i64,
+        hit_points: int(0)?,
+        max_hit_points: int(1)?,
+        mp: int(2)?,
+        x: game_to_logs(number(field(fields, first + 6)?)?),
+        y: game_to_logs(number(field(fields, first + 7)?)?),
+    }))
+}
+
+// Epoch milliseconds of a timestamp like 2024-03-02T21:14:05.1230000-05:00
+fn parse_timestamp(text: &str) -> Option<i64> {
+    let (date, time) = text.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>());
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let (clock, offset) = match time.rfind(['+', '-', 'Z']) {
+        Some(split) => time.split_at(split),
+        None => (time, ""),
+    };
+    let mut clock = clock.splitn(3, ':');
+    let hours: i64 = clock.next()?.parse().ok()?;
+    let minutes: i64 = clock.next()?.parse().ok()?;
+    let seconds = clock.next()?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let seconds: i64 = seconds.parse().ok()?;
+    let millis = format!("{:0<3}", fraction.get(..3).unwrap_or(fraction))
+        .parse::<i64>()
+        .ok()?;
+
+    let offset_minutes = match offset.split_at_checked(1) {
+        Some((sign, rest)) if sign == "+" || sign == "-" => {
+            let (h, m) = rest.split_once(':')?;
+            let minutes = h.parse::<i64>().ok()? * 60 + m.parse::<i64>().ok()?;
+            if sign == "-" {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+        _ => 0,
+    };
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds
+        - offset_minutes * 60;
+    Some(seconds * 1000 + millis)
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::Event;
+
+    use super::{pad_arena, parse, parse_timestamp};
+
+    const TANK: i64 = 0x10FF0001;
+    const BOSS: i64 = 0x40001234;
+
+    fn ability_line(
+        kind: &str,
+        time: &str,
+        flags: &str,
+        damage: &str,
+        target_index: u32,
+    ) -> String {
+        format!(
+            "{}|2024-03-02T21:14:{}-05:00|40001234|Omega|7B3F|Blaster|10FF0001|Tank Main|{}|{}|{}\
+             50000|100000|10000|10000|||95.5|100|0|1.5708|\
+             8000000|8000000|10000|10000|||100|90|0|0|\
+             0000A1B2|{}|2|hash",
+            kind,
+            time,
+            flags,
+            damage,
+            "0|".repeat(14),
+            target_index
+        )
+    }
+
+    #[test]
+    fn act_log_test() {
+        let log = [
+            "01|2024-03-02T21:14:00.0000000-05:00|4EF|The Omega Protocol (Ultimate)|hash".to_string(),
+            "03|2024-03-02T21:14:00.5000000-05:00|10FF0001|Tank Main|13|90|0000|4A|Gilgamesh|0|0|100000|100000|10000|10000|||95.5|100|0|3.1416|hash".to_string(),
+            "261|2024-03-02T21:14:01.0000000-05:00|Add|40001234|BNpcID|3F50|Name|Omega|OwnerID|E0000000|Job|0|PosX|100|PosY|90|PosZ|0|hash".to_string(),
+            "20|2024-03-02T21:14:02.0000000-05:00|40001234|Omega|7B3F|Blaster|10FF0001|Tank Main|4.70|100|90|0|0|hash".to_string(),
+            // One hit split over two lines (each target of an area ability gets one), the second
+            // a crit big enough to need the fourth byte
+            ability_line("22", "06.7000000", "3", "3E800000", 0),
+            ability_line("22", "06.7000000", "2003", "423F400F", 1),
+            "261|2024-03-02T21:14:07.0000000-05:00|Change|40001234|PosX|105|hash".to_string(),
+            // Moves for an actor the log never added still get the actor registered
+            "261|2024-03-02T21:14:07.5000000-05:00|Change|40005678|PosX|100|PosY|100|hash".to_string(),
+            "26|2024-03-02T21:14:08.0000000-05:00|A9F|Doom|30.00|40001234|Omega|10FF0001|Tank Main|00|100000|8000000|hash".to_string(),
+            "27|2024-03-02T21:14:09.0000000-05:00|10FF0001|Tank Main|0000|0000|01D6|0000|0000|0000|hash".to_string(),
+            "35|2024-03-02T21:14:09.5000000-05:00|40001234|Omega|10FF0001|Tank Main|0000|0000|0054|000F|0000|0000|0000|hash".to_string(),
+            "30|2024-03-02T21:14:10.0000000-05:00|A9F|Doom|0.00|40001234|Omega|10FF0001|Tank Main|00|hash".to_string(),
+            "25|2024-03-02T21:14:10.0000000-05:00|10FF0001|Tank Main|40001234|Omega|hash".to_string(),
+            // Lines this doesn't read are skipped
+            "00|2024-03-02T21:14:11.0000000-05:00|0839||The limit gauge resets!|hash".to_string(),
+        ]
+        .join("\n");
+
+        let mut log = parse("test", &log);
+        assert_eq!(log.skipped, Vec::<String>::new());
+        assert_eq!(log.pulls.len(), 1);
+        let pull = log.pulls.remove(0);
+        assert_eq!(pull.fight.name, "The Omega Protocol (Ultimate)");
+        assert_eq!(pull.fight.encounter_id, 0x4EF);
+        assert_eq!(pull.fight.start_time, 500.0);
+        assert_eq!(pull.fight.end_time, 11000.0);
+
+        let tank = &pull.actors[&TANK];
+        assert_eq!(
+            (
+                tank.name.as_str(),
+                tank.type_.as_str(),
+                tank.subtype.as_str()
+            ),
+            ("Tank Main", "Player", "Paladin")
+        );
+        assert_eq!(pull.actors[&BOSS].type_, "NPC");
+        assert_eq!(pull.actors[&0x40005678].name, "");
+        assert_eq!(pull.actors[&0x40005678].type_, "NPC");
+        assert_eq!(pull.fight.enemies, Vec::<String>::new());
+        assert_eq!(pull.abilities.name(0x7B3F), "Blaster");
+        assert_eq!(pull.abilities.name(1_000_000 + 0xA9F), "Doom");
+
+        let summary = pull
+            .events
+            .iter()
+            .map(|event| {
+                let json = serde_json::to_value(event).unwrap();
+                (
+                    json["type"].as_str().unwrap().to_string(),
+                    event.get_timestamp(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("begincast", 2000),
+            ("cast", 6700),
+            ("damage", 6700),
+            ("damage", 6700),
+            ("applydebuff", 8000),
+            ("headmarker", 9000),
+            ("tether", 9500),
+            ("removedebuff", 10000),
+            ("death", 10000),
+        ]
+        .map(|(kind, time)| (kind.to_string(), time));
+        assert_eq!(summary, expected);
+
+        let amounts = pull
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Damage {
+                    amount, hit_type, ..
+                } => Some((*amount, *hit_type)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec![(16000, 1), (999999, 2)]);
+        assert!(matches!(
+            pull.events[5],
+            Event::HeadMarker {
+                marker_id: Some(0x1D6),
+                ..
+            }
+        ));
+
+        // Positions come from both the combatant lines and the hit
+        assert_eq!(
+            pull.positions[&TANK].get_position_at(500.0),
+            (9550.0, 10000.0)
+        );
+        assert_eq!(
+            pull.positions[&BOSS].get_position_at(1000.0),
+            (10000.0, 9000.0)
+        );
+        assert_eq!(
+            pull.positions[&BOSS].get_position_at(7000.0),
+            (10500.0, 9000.0)
+        );
+        // Only the players' positions make up the arena
+        assert_eq!(
+            pull.fight.bounding_box,
+            ((7550.0, 8000.0), (11550.0, 12000.0))
+        );
+    }
+
+    #[test]
+    fn act_log_pulls_test() {
+        let line = |kind: &str, seconds: u32, rest: &str| {
+            format!(
+                "{}|2024-03-02T21:{:02}:{:02}.0000000+00:00|{}|hash",
+                kind,
+                seconds / 60,
+                seconds % 60,
+                rest
+            )
+        };
+        let combatant = |seconds, id: &str, name: &str, x: u32| {
+            line(
+                "261",
+                seconds,
+                &format!("Add|{}|Name|{}|Job|19|PosX|{}|PosY|100", id, name, x),
+            )
+        };
+        let log = [
+            line("01", 0, "3DE|Somewhere Else"),
+            line("33", 1, "80037569|40000001|0|0|0|0"),
+            line("33", 2, "80037569|40000005|0|0|0|0"),
+            line("01", 10, "4EF|The Omega Protocol (Ultimate)"),
+            combatant(11, "10FF0001", "Tank Main", 90),
+            combatant(11, "10FF0002", "Healer Main", 110),
+            combatant(11, "40001234", "Omega", 200),
+            line("33", 20, "80037569|40000001|0|0|0|0"),
+            // Unreadable lines are skipped, and the rest of the log still counts
+            line("03", 25, "not an actor"),
+            line("33", 30, "80037569|40000005|0|0|0|0"),
+            // Walking back after a wipe is the lead-up to the next pull
+            line("261", 40, "Change|10FF0001|PosX|95"),
+            line("33", 50, "80037569|40000006|0|0|0|0"),
+            line("33", 110, "80037569|40000003|0|0|0|0"),
+        ]
+        .join("\n");
+
+        let log = parse("test", &log);
+        assert_eq!(log.skipped.len(), 1);
+        assert!(log.skipped[0].starts_with("line 9: "));
+
+        let fights = log
+            .pulls
+            .iter()
+            .map(|pull| {
+                (
+                    pull.fight.id,
+                    pull.fight.name.as_str(),
+                    pull.fight.start_time,
+                    pull.fight.end_time,
+                    pull.fight.kill,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fights,
+            vec![
+                (1, "Somewhere Else", 1000.0, 2000.0, false),
+                (2, "The Omega Protocol (Ultimate)", 20000.0, 30000.0, false),
+                (3, "The Omega Protocol (Ultimate)", 50000.0, 110000.0, true),
+            ]
+        );
+
+        // The boss stands well outside the players, and doesn't count towards the arena
+        assert_eq!(
+            log.pulls[1].fight.bounding_box,
+            ((8000.0, 8000.0), (12000.0, 12000.0))
+        );
+        assert_eq!(log.pulls[1].actors.len(), 3);
+        assert!(log.pulls[2].actors.contains_key(&0x10FF0001));
+    }
+
+    #[test]
+    fn arena_test() {
+        // Someone standing still the whole pull still gets an arena around them
+        let log = [
+            "261|2024-03-02T21:14:00.0000000+00:00|Add|10FF0001|Name|Tank Main|Job|19|PosX|100|PosY|95|hash",
+            "261|2024-03-02T21:14:05.0000000+00:00|Change|10FF0001|PosX|100|PosY|95|hash",
+            "261|2024-03-02T21:14:10.0000000+00:00|Change|10FF0001|PosX|100|PosY|95|hash",
+        ]
+        .join("\n");
+        let pull = parse("test", &log).pulls.remove(0);
+        assert_eq!(
+            pull.fight.bounding_box,
+            ((8000.0, 7500.0), (12000.0, 11500.0))
+        );
+
+        // Bigger spreads only get the margin
+        assert_eq!(
+            pad_arena(((0.0, 1000.0), (10000.0, 1000.0))),
+            ((-500.0, -1000.0), (10500.0, 3000.0))
+        );
+    }
+
+    #[test]
+    fn parse_timestamp_test() {
+        assert_eq!(
+            parse_timestamp("1970-01-01T00:00:01.5000000+00:00"),
+            Some(1500)
+        );
+        assert_eq!(
+            parse_timestamp("2024-03-02T21:14:05.1230000-05:00"),
+            Some(1709432045123)
+        );
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+}
@@ -0,0 +1,438 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt::Write,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::Event,
+    export::csv_field,
+    hits::{damage_taken, killing_blow},
+    report::{format_fight_time, Pull},
+};
+
+#[derive(Debug, Deserialize)]
+struct EncounterConfig {
+    // Ability IDs nobody should be getting hit by
+    avoidable: Vec<i64>,
+}
+
+/// Which abilities count as avoidable, by encounter ID. Read from JSON like
+/// `{"86": {"avoidable": [31001, 31002]}}`; anything else in an encounter's entry (a name, notes) is
+/// ignored.
+#[derive(Debug, Default, Deserialize)]
+pub struct AvoidableConfig(HashMap<i64, EncounterConfig>);
+impl AvoidableConfig {
+    /// Reads the config, or an empty one if there's no file at `path` so that deaths still get
+    /// reported.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                format!("bad avoidable ability config {}: {}", path.display(), e).into()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!(
+                    "No avoidable ability config at {}; only deaths will be listed",
+                    path.display()
+                );
+                Ok(AvoidableConfig::default())
+            }
+            Err(e) => Err(format!("couldn't read {}: {}", path.display(), e).into()),
+        }
+    }
+
+    fn avoidable(&self, encounter_id: i64) -> &[i64] {
+        self.0
+            .get(&encounter_id)
+            .map_or(&[], |encounter| encounter.avoidable.as_slice())
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct AbilityDamage {
+    pub ability_id: i64,
+    pub ability: String,
+    pub hits: u32,
+    pub damage: i64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct KillingBlow {
+    pub ability_id: i64,
+    pub ability: String,
+    pub amount: i64,
+    pub overkill: i64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PlayerDeath {
+    // Milliseconds since the start of the pull
+    pub time: f64,
+    pub killing_blow: Option<KillingBlow>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PlayerFailures {
+    pub id: i64,
+    pub name: String,
+    pub job: String,
+    // Totals over every avoidable ability
+    pub hits: u32,
+    pub damage: i64,
+    pub abilities: Vec<AbilityDamage>,
+    pub deaths: Vec<PlayerDeath>,
+}
+
+pub fn damage_report(pull: &Pull, config: &AvoidableConfig) -> DamageReport {
+    let avoidable = config.avoidable(pull.fight.encounter_id);
+    if avoidable.is_empty() {
+        println!(
+            "No avoidable abilities configured for {} (encounter {})",
+            pull.fight.name, pull.fight.encounter_id
+        );
+    }
+    let hits = damage_taken(pull);
+
+    // pull.actors holds every actor in the report, so only list the players
+    // who moved or took part in an event during this pull
+    let participants = pull
+        .events
+        .iter()
+        .flat_map(|event| {
+            let (source, target) = event.get_actor_ids();
+            source.into_iter().chain(target)
+        })
+        .chain(pull.positions.keys().copied())
+        .collect::<HashSet<_>>();
+
+    let mut players = pull
+        .actors
+        .iter()
+        .filter(|(id, actor)| actor.type_ == "Player" && participants.contains(id))
+        .map(|(id, actor)| PlayerFailures {
+            id: *id,
+            name: actor.name.clone(),
+            job: actor.subtype.clone(),
+            hits: 0,
+            damage: 0,
+            abilities: Vec::new(),
+            deaths: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    for player in &mut players {
+        let mut abilities: BTreeMap<i64, AbilityDamage> = BTreeMap::new();
+        for hit in &hits {
+            if hit.target != player.id || !avoidable.contains(&hit.ability_id) {
+                continue;
+            }
+            let ability = abilities.entry(hit.ability_id).or_insert(AbilityDamage {
+                ability_id: hit.ability_id,
+                ability: hit.name.clone(),
+                hits: 0,
+                damage: 0,
+            });
+            ability.hits += 1;
+            ability.damage += hit.amount;
+            player.hits += 1;
+            player.damage += hit.amount;
+        }
+        player.abilities = abilities.into_values().collect();
+        player
+            .abilities
+            .sort_by(|a, b| b.damage.cmp(&a.damage).then(b.hits.cmp(&a.hits)));
+
+        for event in &pull.events {
+            if let Event::Death {
+                target, timestamp, ..
+            } = event
+            {
+                if target.id != player.id {
+                    continue;
+                }
+                let timestamp = *timestamp as f64;
+                player.deaths.push(PlayerDeath {
+                    time: timestamp - pull.fight.start_time,
+                    killing_blow: killing_blow(&hits, player.id, timestamp).map(|hit| {
+                        KillingBlow {
+                            ability_id: hit.ability_id,
+                            ability: hit.name.clone(),
+                            amount: hit.amount,
+                            overkill: hit.overkill,
+                        }
+                    }),
+                });
+            }
+        }
+    }
+
+    // Worst offenders first
+    players.sort_by(|a, b| {
+        b.deaths
+            .len()
+            .cmp(&a.deaths.len())
+            .then(b.damage.cmp(&a.damage))
+            .then(b.hits.cmp(&a.hits))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    DamageReport {
+        code: pull.code.clone(),
+        fight_id: pull.fight.id,
+        encounter_id: pull.fight.encounter_id,
+        encounter: pull.fight.name.clone(),
+        players,
+    }
+}
+
+/// How to write out a damage report, picked from the output file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Table,
+    Json,
+    Csv,
+}
+impl ReportFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("txt") => Ok(ReportFormat::Table),
+            Some("json") => Ok(ReportFormat::Json),
+            Some("csv") => Ok(ReportFormat::Csv),
+            _ => Err(format!(
+                "can't tell the report format of {}; use .txt, .json or .csv",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+fn killing_blow_text(death: &PlayerDeath) -> String {
+    match &death.killing_blow {
+        Some(blow) if blow.overkill > 0 => format!(
+            "{} ({}, {} overkill)",
+            blow.ability, blow.amount, blow.overkill
+        ),
+        Some(blow) => format!("{} ({})", blow.ability, blow.amount),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Who got hit by what avoidable mechanics in a pull, and who died to what.
+#[derive(Debug, Serialize)]
+pub struct DamageReport {
+    pub code: String,
+    pub fight_id: i64,
+    pub encounter_id: i64,
+    pub encounter: String,
+    pub players: Vec<PlayerFailures>,
+}
+
+impl DamageReport {
+    pub fn to_table(&self) -> String {
+        let name_width = self
+            .players
+            .iter()
+            .map(|player| player.name.len())
+            .chain(["Player".len()])
+            .max()
+            .unwrap();
+        let job_width = self
+            .players
+            .iter()
+            .map(|player| player.job.len())
+            .chain(["Job".len()])
+            .max()
+            .unwrap();
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "Avoidable damage in {} #{} ({})",
+            self.code, self.fight_id, self.encounter
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "{:name_width$}  {:job_width$}  {:>5}  {:>9}  {:>6}",
+            "Player", "Job", "Hits", "Damage", "Deaths"
+        )
+        .unwrap();
+        for player in &self.players {
+            writeln!(
+                out,
+                "{:name_width$}  {:job_width$}  {:>5}  {:>9}  {:>6}",
+                player.name,
+                player.job,
+                player.hits,
+                player.damage,
+                player.deaths.len()
+            )
+            .unwrap();
+        }
+
+        for player in &self.players {
+            if player.abilities.is_empty() && player.deaths.is_empty() {
+                continue;
+            }
+            writeln!(out, "\n{}", player.name).unwrap();
+            for ability in &player.abilities {
+                writeln!(
+                    out,
+                    "  {}: {} hit{}, {} damage",
+                    ability.ability,
+                    ability.hits,
+                    if ability.hits == 1 { "" } else { "s" },
+                    ability.damage
+                )
+                .unwrap();
+            }
+            for death in &player.deaths {
+                writeln!(
+                    out,
+                    "  died at {} to {}",
+                    format_fight_time(death.time),
+                    killing_blow_text(death)
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+
+    /// One row per player total, per avoidable ability a player got hit by, and per death.
+    pub fn to_csv(&self) -> String {
+        let mut out = "player,job,kind,ability_id,ability,time,hits,damage,overkill\n".to_string();
+        for player in &self.players {
+            let name = csv_field(&player.name);
+            let job = csv_field(&player.job);
+            writeln!(
+                out,
+                "{},{},total,,,,{},{},",
+                name, job, player.hits, player.damage
+            )
+            .unwrap();
+            for ability in &player.abilities {
+                writeln!(
+                    out,
+                    "{},{},ability,{},{},,{},{},",
+                    name,
+                    job,
+                    ability.ability_id,
+                    csv_field(&ability.ability),
+                    ability.hits,
+                    ability.damage
+                )
+                .unwrap();
+            }
+            for death in &player.deaths {
+                let time = format_fight_time(death.time);
+                match &death.killing_blow {
+                    Some(blow) => writeln!(
+                        out,
+                        "{},{},death,{},{},{},,{},{}",
+                        name,
+                        job,
+                        blow.ability_id,
+                        csv_field(&blow.ability),
+                        time,
+                        blow.amount,
+                        blow.overkill
+                    ),
+                    None => writeln!(out, "{},{},death,,,{},,,", name, job, time),
+                }
+                .unwrap();
+            }
+        }
+
+        out
+    }
+
+    /// Prints the report as a table, or writes it to `output` in the format its extension asks for.
+    pub fn write(&self, output: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        let Some(output) = output else {
+            print!("{}", self.to_table());
+            return Ok(());
+        };
+
+        let contents = match ReportFormat::from_path(output)? {
+            ReportFormat::Table => self.to_table(),
+            ReportFormat::Json => serde_json::to_string_pretty(self)?,
+            ReportFormat::Csv => self.to_csv(),
+        };
+        if let Some(dir) = output.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(output, contents)?;
+        println!("Wrote damage report to {}", output.display());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::test_util::{actor, pull_with};
+
+    use super::{damage_report, AvoidableConfig};
+
+    #[test]
+    fn damage_report_test() {
+        let config: AvoidableConfig =
+            serde_json::from_str(r#"{"86": {"name": "Hephaistos", "avoidable": [31001]}}"#)
+                .unwrap();
+        let mut pull = pull_with(
+            [
+                (1, actor("Tank, Main", "Player", "Paladin")),
+                (2, actor("Caster", "Player", "BlackMage")),
+                // In the report, but not in this pull
+                (3, actor("Benched", "Player", "WhiteMage")),
+                (100, actor("Hephaistos", "NPC", "Boss")),
+            ],
+            serde_json::json!([
+                {"type": "damage", "abilityGameID": 31000, "amount": 20000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 11000},
+                {"type": "damage", "abilityGameID": 31001, "amount": 30000, "hitType": 1, "sourceID": 100, "targetID": 2, "timestamp": 12000},
+                {"type": "damage", "abilityGameID": 31001, "amount": 90000, "overkill": 10000, "hitType": 1, "sourceID": 100, "targetID": 2, "timestamp": 15000},
+                {"type": "death", "sourceID": 100, "targetID": 2, "timestamp": 15001}
+            ]),
+        );
+        pull.fight.start_time = 10000.0;
+        pull.fight.end_time = 20000.0;
+        // Nobody has position samples, which mustn't keep them out of the report
+        assert!(pull.positions.is_empty());
+
+        let report = damage_report(&pull, &config);
+        assert_eq!(
+            report
+                .players
+                .iter()
+                .map(|player| (
+                    player.name.as_str(),
+                    player.hits,
+                    player.damage,
+                    player.deaths.len()
+                ))
+                .collect::<Vec<_>>(),
+            vec![("Caster", 2, 120000, 1), ("Tank, Main", 0, 0, 0)]
+        );
+        assert_eq!(report.players[0].deaths[0].time, 5001.0);
+
+        assert_eq!(
+            report.to_csv(),
+            "player,job,kind,ability_id,ability,time,hits,damage,overkill\n\
+             Caster,BlackMage,total,,,,2,120000,\n\
+             Caster,BlackMage,ability,31001,#31001,,2,120000,\n\
+             Caster,BlackMage,death,31001,#31001,0:05.0,,90000,10000\n\
+             \"Tank, Main\",Paladin,total,,,,0,0,\n"
+        );
+    }
+}
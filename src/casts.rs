@@ -0,0 +1,163 @@
+use crate::{events::Event, report::Pull};
+
+/// A cast by an enemy, from the start of its cast bar (if it had one) until it went off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BossCast {
+    pub source: i64,
+    pub ability_id: i64,
+    pub name: String,
+    pub begin: f64,
+    // Cast bar length in milliseconds; 0 for instant casts
+    pub duration: f64,
+    // When the cast went off, or when the bar would have finished if it was interrupted
+    pub end: f64,
+    // False for cast bars that never went off
+    pub completed: bool,
+}
+impl BossCast {
+    /// How far along the cast bar is at `timestamp`, if it's being cast at that moment.
+    pub fn progress(&self, timestamp: f64) -> Option<f64> {
+        if self.duration > 0.0 && self.begin <= timestamp && timestamp < self.end {
+            Some(((timestamp - self.begin) / self.duration).clamp(0.0, 1.0))
+        } else {
+            None
+        }
+    }
+}
+
+/// Every cast by enemies in the pull, in the order they started. Auto-attacks are left out.
+pub fn boss_casts(pull: &Pull) -> Vec<BossCast> {
+    let is_enemy = |id: i64| {
+        pull.actors
+            .get(&id)
+            .is_some_and(|actor| actor.type_ == "NPC")
+    };
+
+    let mut casts: Vec<BossCast> = Vec::new();
+    for event in &pull.events {
+        match event {
+            Event::BeginCast {
+                ability_game_id,
+                duration,
+                source,
+                timestamp,
+                ..
+            } if is_enemy(source.id) => casts.push(BossCast {
+                source: source.id,
+                ability_id: *ability_game_id,
+                name: pull.abilities.name(*ability_game_id),
+                begin: *timestamp as f64,
+                duration: *duration as f64,
+                end: (*timestamp + *duration) as f64,
+                completed: false,
+            }),
+            Event::Cast {
+                ability_game_id,
+                source,
+                timestamp,
+                ..
+            } if is_enemy(source.id) => {
+                // Finishes the latest matching cast bar if there is one, otherwise it was instant.
+                let started = casts.iter_mut().rev().find(|cast| {
+                    !cast.completed
+                        && cast.source == source.id
+                        && cast.ability_id == *ability_game_id
+                        && cast.duration > 0.0
+                        && cast.begin <= *timestamp as f64
+                        && *timestamp as f64 <= cast.end + 1000.0
+                });
+                match started {
+                    Some(cast) => {
+                        cast.end = *timestamp as f64;
+                        cast.completed = true;
+                    }
+                    None => {
+                        let name = pull.abilities.name(*ability_game_id);
+                        if !name.eq_ignore_ascii_case("attack") {
+                            casts.push(BossCast {
+                                source: source.id,
+                                ability_id: *ability_game_id,
+                                name,
+                                begin: *timestamp as f64,
+                                duration: 0.0,
+                                end: *timestamp as f64,
+                                completed: true,
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    casts
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        abilities::{AbilityInfo, AbilityTable},
+        report::test_util::{actor, pull_with},
+    };
+
+    use super::{boss_casts, BossCast};
+
+    #[test]
+    fn boss_casts_test() {
+        let mut abilities = AbilityTable::default();
+        for (id, name) in [
+            (7, "attack"),
+            (31000, "Gaoler's Flail"),
+            (31001, "Abyssal Fires"),
+        ] {
+            abilities.insert(
+                id,
+                AbilityInfo {
+                    name: name.to_string(),
+                    icon: String::new(),
+                    type_: "1024".to_string(),
+                },
+            );
+        }
+        let mut pull = pull_with(
+            [
+                (1, actor("Player", "Player", "Paladin")),
+                (100, actor("Boss", "NPC", "Boss")),
+            ],
+            serde_json::json!([
+                {"type": "begincast", "abilityGameID": 31000, "duration": 3000, "sourceID": 100, "targetID": -1, "timestamp": 1000},
+                {"type": "cast", "abilityGameID": 31000, "sourceID": 100, "targetID": -1, "timestamp": 3900},
+                {"type": "cast", "abilityGameID": 7, "sourceID": 100, "targetID": 1, "timestamp": 4000},
+                {"type": "begincast", "abilityGameID": 31001, "duration": 5000, "sourceID": 100, "targetID": -1, "timestamp": 5000},
+                {"type": "cast", "abilityGameID": 31000, "sourceID": 100, "targetID": -1, "timestamp": 8000},
+                {"type": "begincast", "abilityGameID": 7, "duration": 1500, "sourceID": 1, "targetID": 100, "timestamp": 8500}
+            ]),
+        );
+        pull.abilities = abilities;
+        pull.fight.kill = true;
+
+        let cast = |ability_id, name: &str, begin, duration, end, completed| BossCast {
+            source: 100,
+            ability_id,
+            name: name.to_string(),
+            begin,
+            duration,
+            end,
+            completed,
+        };
+        let casts = boss_casts(&pull);
+        assert_eq!(
+            casts,
+            vec![
+                cast(31000, "Gaoler's Flail", 1000.0, 3000.0, 3900.0, true),
+                cast(31001, "Abyssal Fires", 5000.0, 5000.0, 10000.0, false),
+                cast(31000, "Gaoler's Flail", 8000.0, 0.0, 8000.0, true),
+            ]
+        );
+
+        assert_eq!(casts[0].progress(2500.0), Some(0.5));
+        assert_eq!(casts[0].progress(3900.0), None);
+        assert_eq!(casts[2].progress(8000.0), None);
+    }
+}
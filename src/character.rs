@@ -0,0 +1,130 @@
+use std::error::Error;
+
+use serde_json::Value;
+
+use crate::{client::Client, queries};
+
+/// How to find a character on FF Logs.
+#[derive(Debug, Clone)]
+pub enum CharacterRef {
+    Id(i64),
+    Name {
+        name: String,
+        server: String,
+        region: String,
+    },
+}
+
+/// A kill from a character's encounter rankings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedFight {
+    pub code: String,
+    pub fight_id: i64,
+    pub rank_percent: f64,
+}
+
+// Picks the ranked fights out of the `encounterRankings` JSON, best parse first.
+fn get_ranked_fights(character_rankings: &Value) -> Option<Vec<RankedFight>> {
+    let mut result = Vec::new();
+
+    for rank in character_rankings.as_object()?.get("ranks")?.as_array()? {
+        let rank = rank.as_object()?;
+        let report = rank.get("report")?.as_object()?;
+
+        result.push(RankedFight {
+            code: report.get("code")?.as_str()?.to_string(),
+            fight_id: report.get("fightID")?.as_i64()?,
+            rank_percent: rank
+                .get("rankPercent")
+                .and_then(|percent| percent.as_f64())
+                .unwrap_or(0.0),
+        });
+    }
+
+    result.sort_by(|a, b| b.rank_percent.total_cmp(&a.rank_percent));
+    Some(result)
+}
+
+/// Looks the character up and returns their name along with their ranked fights for the encounter.
+pub async fn load_ranked_fights(
+    client: &Client,
+    character: &CharacterRef,
+    encounter_id: i64,
+) -> Result<(String, Vec<RankedFight>), Box<dyn Error>> {
+    let (id, name, server, region) = match character {
+        CharacterRef::Id(id) => (Some(*id), None, None, None),
+        CharacterRef::Name {
+            name,
+            server,
+            region,
+        } => (
+            None,
+            Some(name.clone()),
+            Some(server.clone()),
+            Some(region.clone()),
+        ),
+    };
+
+    let character_data = client
+        .query::<queries::IndividualCharacter>(queries::individual_character::Variables {
+            id,
+            name,
+            server,
+            region,
+            encounter_id,
+        })
+        .await?
+        .character_data
+        .and_then(|data| data.character)
+        .ok_or_else(|| format!("no character found for {:?}", character))?;
+
+    let ranked_fights = character_data
+        .encounter_rankings
+        .as_ref()
+        .and_then(get_ranked_fights)
+        .ok_or_else(|| {
+            format!(
+                "no rankings for {} in encounter {}",
+                character_data.name, encounter_id
+            )
+        })?;
+
+    Ok((character_data.name, ranked_fights))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_ranked_fights, RankedFight};
+
+    #[test]
+    fn ranked_fights_test() {
+        let rankings = serde_json::json!({
+            "bestAmount": 12000.5,
+            "ranks": [
+                {"rankPercent": 41.2, "report": {"code": "aaa111", "fightID": 7, "startTime": 0}},
+                {"rankPercent": 97.8, "report": {"code": "bbb222", "fightID": 3, "startTime": 0}},
+                {"report": {"code": "ccc333", "fightID": 12, "startTime": 0}}
+            ]
+        });
+
+        let fight = |code: &str, fight_id, rank_percent| RankedFight {
+            code: code.to_string(),
+            fight_id,
+            rank_percent,
+        };
+        assert_eq!(
+            get_ranked_fights(&rankings),
+            Some(vec![
+                fight("bbb222", 3, 97.8),
+                fight("aaa111", 7, 41.2),
+                fight("ccc333", 12, 0.0),
+            ])
+        );
+
+        assert_eq!(
+            get_ranked_fights(&serde_json::json!({"ranks": []})),
+            Some(vec![])
+        );
+        assert_eq!(get_ranked_fights(&serde_json::json!({})), None);
+    }
+}
@@ -1,10 +1,24 @@
-use std::{error::Error, time::Duration};
+use std::{
+    collections::hash_map::RandomState,
+    error::Error,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use graphql_client::GraphQLQuery;
-use reqwest::header::HeaderValue;
+use graphql_client::{GraphQLQuery, QueryBody, Response};
+use reqwest::{header::HeaderValue, StatusCode};
+use serde::Serialize;
+
+use crate::rate_limit::{self, OnLowBudget, RateBudget, RateLimiter};
 
 const FFLOGS_API_URL: &str = "https://www.fflogs.com/api/v2/client";
-const RATE_LIMIT_POINT_THRESHOLD: f64 = 100.0;
+
+// Transient failures (timeouts, 5xx, 429) are retried this many times in total, backing off
+// exponentially from the initial delay up to the maximum.
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 
 pub struct RateLimitInfo {
     pub limit_per_hour: i64,
@@ -18,7 +32,11 @@ pub trait RateLimitableQuery: GraphQLQuery {
 }
 
 pub struct Client {
-    client: reqwest::Client,
+    // None when running offline, in which case every query has to be answered from the cache.
+    client: Option<reqwest::Client>,
+    cache_dir: Option<PathBuf>,
+    // Shared by every query, including concurrent ones
+    rate_limiter: RateLimiter,
 }
 impl Client {
     pub fn new(api_token: &str) -> Result<Self, Box<dyn Error>> {
@@ -33,44 +51,235 @@ impl Client {
             )
             .build()?;
 
-        Ok(Client { client })
+        Ok(Client {
+            client: Some(client),
+            cache_dir: None,
+            rate_limiter: RateLimiter::new(rate_limit::DEFAULT_THRESHOLD, OnLowBudget::Wait),
+        })
+    }
+
+    /// A client that never touches the network. Only useful together with a cache directory.
+    pub fn offline() -> Self {
+        Client {
+            client: None,
+            cache_dir: None,
+            rate_limiter: RateLimiter::new(rate_limit::DEFAULT_THRESHOLD, OnLowBudget::Wait),
+        }
+    }
+
+    /// Stores every successful response under `dir` and answers repeated queries from there.
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Keeps at least `threshold` points of the hourly budget unspent, either waiting for the
+    /// budget to reset or failing queries once it runs that low.
+    pub fn with_rate_limit(mut self, threshold: f64, on_low: OnLowBudget) -> Self {
+        self.rate_limiter = RateLimiter::new(threshold, on_low);
+        self
+    }
+
+    /// What's left of the hourly API budget, as far as this client knows.
+    pub fn rate_budget(&self) -> Option<RateBudget> {
+        self.rate_limiter.budget()
     }
 
     pub async fn query<Q: RateLimitableQuery>(
         &self,
         vars: <Q as GraphQLQuery>::Variables,
     ) -> Result<<Q as GraphQLQuery>::ResponseData, Box<dyn Error>> {
-        let resp =
-            graphql_client::reqwest::post_graphql::<Q, _>(&self.client, FFLOGS_API_URL, vars)
-                .await?;
-
-        if let Some(errors) = resp.errors {
-            let mut error_text = String::new();
-            for err in errors {
-                error_text.push_str(&format!("{}", err));
-                error_text.push_str("\n");
+        let body = Q::build_query(vars);
+        let cache_path = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| cache_file_path(dir, &body))
+            .transpose()?;
+
+        if let Some(cache_path) = &cache_path {
+            if let Ok(text) = std::fs::read_to_string(cache_path) {
+                return parse_response::<Q>(&text);
             }
-            return Err(error_text.into());
         }
 
-        let resp = resp.data.expect("no errors and no data in response");
+        let client = self.client.as_ref().ok_or_else(|| match &cache_path {
+            Some(cache_path) => format!(
+                "{} is not cached (no {}) and the client is offline",
+                body.operation_name,
+                cache_path.display()
+            ),
+            None => format!(
+                "{} is not cached and the client is offline",
+                body.operation_name
+            ),
+        })?;
+
+        self.rate_limiter.reserve(body.operation_name).await?;
+
+        let mut attempt = 1;
+        let text = loop {
+            match send_query(client, &body).await {
+                Ok(text) => break text,
+                Err(failed) if failed.transient && attempt < MAX_ATTEMPTS => {
+                    let delay = failed.retry_after.unwrap_or_else(|| retry_delay(attempt));
+                    println!(
+                        "{} failed ({}), retrying in {:?}",
+                        body.operation_name, failed.message, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(failed) => return Err(failed.message.into()),
+            }
+        };
+        let resp = parse_response::<Q>(&text)?;
+
+        if let Some(cache_path) = &cache_path {
+            std::fs::create_dir_all(cache_path.parent().unwrap())?;
+            std::fs::write(cache_path, &text)?;
+        }
 
         if let Some(rate_limit_data) = Q::get_rate_limit_data(&resp) {
-            Self::enforce_rate_limit(&rate_limit_data).await?;
+            self.rate_limiter
+                .update(body.operation_name, &rate_limit_data);
         }
 
         Ok(resp)
     }
 
-    async fn enforce_rate_limit(data: &RateLimitInfo) -> Result<(), Box<dyn Error>> {
-        let points_remaining = data.limit_per_hour as f64 - data.points_spent_this_hour;
+    /// Puts a raw response into the cache as if it had been fetched for `vars`.
+    #[cfg(test)]
+    pub fn seed_cache<Q: RateLimitableQuery>(
+        &self,
+        vars: <Q as GraphQLQuery>::Variables,
+        response: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let cache_path = cache_file_path(self.cache_dir.as_ref().unwrap(), &Q::build_query(vars))?;
+        std::fs::create_dir_all(cache_path.parent().unwrap())?;
+        std::fs::write(cache_path, response)?;
+        Ok(())
+    }
+}
 
-        if points_remaining < RATE_LIMIT_POINT_THRESHOLD {
-            let time_to_wait = Duration::from_secs(data.points_reset_in.try_into()?);
-            println!("Sleeping for {:?} for rate limit!", time_to_wait);
-            tokio::time::sleep(time_to_wait).await;
+// Why a request failed, kept as plain data since it's held across the retry sleep.
+struct FailedRequest {
+    message: String,
+    // Whether the same request could succeed if tried again
+    transient: bool,
+    // How long the server asked us to wait before trying again
+    retry_after: Option<Duration>,
+}
+
+async fn send_query<V: Serialize>(
+    client: &reqwest::Client,
+    body: &QueryBody<V>,
+) -> Result<String, FailedRequest> {
+    let failed = |e: reqwest::Error, transient: bool| FailedRequest {
+        message: e.to_string(),
+        transient,
+        retry_after: None,
+    };
+
+    let response = client
+        .post(FFLOGS_API_URL)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| {
+            let transient = e.is_timeout() || e.is_connect() || e.is_request();
+            failed(e, transient)
+        })?;
+
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        // Only the delay-seconds form of Retry-After; FF Logs doesn't send dates.
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        return Err(FailedRequest {
+            message: format!("HTTP {}", status),
+            transient: true,
+            retry_after,
+        });
+    }
+
+    response
+        .error_for_status()
+        .map_err(|e| failed(e, false))?
+        .text()
+        .await
+        .map_err(|e| failed(e, true))
+}
+
+// Exponential backoff with jitter, so concurrent requests that failed together don't all retry at
+// the same moment. `attempt` counts from 1.
+fn retry_delay(attempt: u32) -> Duration {
+    let backoff = INITIAL_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_RETRY_DELAY);
+
+    // RandomState is seeded randomly, which is plenty for jitter without pulling in a rand crate.
+    let random = RandomState::new().build_hasher().finish();
+    backoff.mul_f64(0.5 + (random % 1000) as f64 / 2000.0)
+}
+
+fn parse_response<Q: GraphQLQuery>(
+    text: &str,
+) -> Result<<Q as GraphQLQuery>::ResponseData, Box<dyn Error>> {
+    let resp: Response<<Q as GraphQLQuery>::ResponseData> = serde_json::from_str(text)?;
+
+    if let Some(errors) = resp.errors {
+        let mut error_text = String::new();
+        for err in errors {
+            error_text.push_str(&format!("{}", err));
+            error_text.push('\n');
         }
+        return Err(error_text.into());
+    }
 
-        Ok(())
+    Ok(resp.data.expect("no errors and no data in response"))
+}
+
+// Cache files are named after the operation plus a hash of the full request body, so the same
+// query with different variables gets its own entry.
+fn cache_file_path<V: Serialize>(
+    dir: &Path,
+    body: &QueryBody<V>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    // FNV-1a, which unlike the std hasher is stable across runs and compiler versions.
+    let hash = serde_json::to_vec(body)?
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+    Ok(dir.join(format!("{}-{:016x}.json", body.operation_name, hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{retry_delay, MAX_RETRY_DELAY};
+
+    #[test]
+    fn retry_delay_test() {
+        for _ in 0..20 {
+            let first = retry_delay(1);
+            assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+
+            let third = retry_delay(3);
+            assert!(third >= Duration::from_secs(2) && third <= Duration::from_secs(4));
+
+            assert!(retry_delay(40) <= MAX_RETRY_DELAY);
+        }
     }
 }
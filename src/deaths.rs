@@ -0,0 +1,434 @@
+use std::{error::Error, fmt::Write, path::Path};
+
+use crate::{
+    events::Event,
+    hits::{damage_taken, killing_blow, DamageHit},
+    positions::{Interpolation, Position},
+    report::{format_fight_time, Pull},
+    video::{render_death_snapshot, OverlaySource},
+};
+
+// Seconds of lead-up shown before each death unless asked otherwise
+pub const DEFAULT_RECAP_WINDOW: f64 = 15.0;
+
+// Milliseconds between trail points when following anything but straight lines between samples
+const TRAIL_STEP: f64 = 100.0;
+
+const SPARKLINE: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecapKind {
+    Damage,
+    Heal,
+    Gained,
+    Lost,
+}
+impl RecapKind {
+    fn text(self) -> &'static str {
+        match self {
+            RecapKind::Damage => "damage",
+            RecapKind::Heal => "heal",
+            RecapKind::Gained => "gained",
+            RecapKind::Lost => "lost",
+        }
+    }
+}
+
+/// Something that happened to the player shortly before they died.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecapEvent {
+    // Milliseconds since the start of the pull
+    pub time: f64,
+    pub kind: RecapKind,
+    pub ability: String,
+    pub amount: Option<i64>,
+    pub source: String,
+    // Hit points and max hit points right after the event, if the log says
+    pub hp: Option<(i64, i64)>,
+}
+
+/// The lead-up to one player's death.
+#[derive(Debug, Clone)]
+pub struct DeathRecap {
+    pub name: String,
+    pub job: String,
+    // When they died, in report time
+    pub timestamp: f64,
+    pub killing_blow: Option<DamageHit>,
+    pub events: Vec<RecapEvent>,
+    // (report time, hit points, max hit points) from every event that carried their resources
+    pub hp: Vec<(f64, i64, i64)>,
+    // Where they were over the window, oldest first
+    pub trail: Vec<Position>,
+}
+impl DeathRecap {
+    /// How far they moved over the window, in yalms.
+    pub fn distance_moved(&self) -> f64 {
+        self.trail
+            .windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1))
+            .sum::<f64>()
+            / 100.0
+    }
+
+    /// Their HP as a percentage of max over the window, one character per second.
+    pub fn hp_sparkline(&self, window: f64) -> String {
+        let start = self.timestamp - window * 1000.0;
+        let mut line = String::new();
+        let mut current = self
+            .hp
+            .iter()
+            .rev()
+            .find(|(time, _, _)| *time <= start)
+            .map(|(_, hp, max)| (*hp, *max));
+        let mut samples = self.hp.iter().peekable();
+
+        for second in 1..=window.ceil() as usize {
+            let until = start + second as f64 * 1000.0;
+            while let Some((_, hp, max)) = samples.next_if(|(time, _, _)| *time <= until) {
+                current = Some((*hp, *max));
+            }
+            line.push(match current {
+                Some((hp, max)) if max > 0 => {
+                    let fraction = (hp as f64 / max as f64).clamp(0.0, 1.0);
+                    SPARKLINE[((fraction * 7.0).round() as usize).min(7)]
+                }
+                _ => ' ',
+            });
+        }
+
+        line
+    }
+}
+
+/// A recap of every player death in the pull, covering the `window` seconds before each. Trails
+/// fill in between positions with `interpolation`.
+pub fn death_recaps(pull: &Pull, window: f64, interpolation: Interpolation) -> Vec<DeathRecap> {
+    let window = window * 1000.0;
+    let hits = damage_taken(pull);
+    let actor_name = |id: i64| {
+        pull.actors
+            .get(&id)
+            .map_or_else(|| "environment".to_string(), |actor| actor.name.clone())
+    };
+
+    let mut recaps = Vec::new();
+    for event in &pull.events {
+        let Event::Death {
+            target, timestamp, ..
+        } = event
+        else {
+            continue;
+        };
+        let Some(actor) = pull
+            .actors
+            .get(&target.id)
+            .filter(|actor| actor.type_ == "Player")
+        else {
+            continue;
+        };
+        let player = target.id;
+        let death_time = *timestamp as f64;
+        let in_window = |time: f64| death_time - window <= time && time <= death_time;
+
+        let mut events = Vec::new();
+        let mut hp = Vec::new();
+        for event in &pull.events {
+            let time = event.get_timestamp() as f64;
+            if !in_window(time) {
+                continue;
+            }
+
+            let target_hp = event
+                .get_target_resources()
+                .filter(|(id, _)| *id == player)
+                .map(|(_, resources)| (resources.hit_points, resources.max_hit_points));
+            for (id, resources) in [event.get_source_resources(), event.get_target_resources()]
+                .into_iter()
+                .flatten()
+            {
+                if id == player {
+                    hp.push((time, resources.hit_points, resources.max_hit_points));
+                }
+            }
+
+            let (kind, ability, amount, source) = match event {
+                Event::Heal {
+                    ability_game_id,
+                    amount,
+                    source,
+                    target,
+                    ..
+                } if target.id == player => {
+                    (RecapKind::Heal, *ability_game_id, Some(*amount), source.id)
+                }
+                Event::ApplyBuff {
+                    ability_game_id,
+                    source,
+                    target,
+                    ..
+                }
+                | Event::ApplyDebuff {
+                    ability_game_id,
+                    source,
+                    target,
+                    ..
+                } if target.id == player => (RecapKind::Gained, *ability_game_id, None, source.id),
+                Event::RemoveBuff {
+                    ability_game_id,
+                    source,
+                    target,
+                    ..
+                }
+                | Event::RemoveDebuff {
+                    ability_game_id,
+                    source,
+                    target,
+                    ..
+                } if target.id == player => (RecapKind::Lost, *ability_game_id, None, source.id),
+                _ => continue,
+            };
+            events.push(RecapEvent {
+                time: time - pull.fight.start_time,
+                kind,
+                ability: pull.abilities.name(ability),
+                amount,
+                source: actor_name(source),
+                hp: target_hp,
+            });
+        }
+
+        // Damage comes from the hit list so snapshotted hits that never landed aren't doubled up
+        for hit in hits
+            .iter()
+            .filter(|hit| hit.target == player && in_window(hit.timestamp))
+        {
+            events.push(RecapEvent {
+                time: hit.timestamp - pull.fight.start_time,
+                kind: RecapKind::Damage,
+                ability: hit.name.clone(),
+                amount: Some(hit.amount),
+                source: actor_name(hit.source),
+                hp: hit.hp,
+            });
+        }
+        events.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let trail = match pull
+            .positions
+            .get(&player)
+            .filter(|history| !history.is_empty())
+        {
+            Some(history) => {
+                let start = (death_time - window).max(pull.fight.start_time);
+                if interpolation == Interpolation::Linear {
+                    // Every recorded position in between, so no corners get cut
+                    std::iter::once(history.get_position_at(start))
+                        .chain(
+                            history
+                                .samples_between(start, death_time)
+                                .map(|(_, position)| position),
+                        )
+                        .chain([history.get_position_at(death_time)])
+                        .collect()
+                } else {
+                    let steps = ((death_time - start) / TRAIL_STEP).ceil().max(1.0) as usize;
+                    let mut trail = vec![(0.0, 0.0); steps + 1];
+                    history.resample_into(
+                        start,
+                        (death_time - start) / steps as f64,
+                        interpolation,
+                        &mut trail,
+                    );
+                    trail
+                }
+            }
+            None => Vec::new(),
+        };
+
+        recaps.push(DeathRecap {
+            name: actor.name.clone(),
+            job: actor.subtype.clone(),
+            timestamp: death_time,
+            killing_blow: killing_blow(&hits, player, death_time).cloned(),
+            events,
+            hp,
+            trail,
+        });
+    }
+
+    recaps
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// Writes the recaps as a markdown document at `output`, with a snapshot of the arena at each death
+/// saved next to it as a PNG.
+pub fn write_recaps(
+    pull: &Pull,
+    recaps: &[DeathRecap],
+    window: f64,
+    image_size: u32,
+    interpolation: Interpolation,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let stem = output
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let source = OverlaySource::from_pull(pull, interpolation);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# Deaths in {} #{} ({}, {})\n",
+        pull.code,
+        pull.fight.id,
+        pull.fight.name,
+        pull.fight.outcome_text()
+    )?;
+    if recaps.is_empty() {
+        writeln!(out, "Nobody died.")?;
+    }
+
+    for (i, recap) in recaps.iter().enumerate() {
+        let time = format_fight_time(recap.timestamp - pull.fight.start_time);
+        writeln!(out, "## {} ({}) died at {}\n", recap.name, recap.job, time)?;
+        match &recap.killing_blow {
+            Some(hit) if hit.overkill > 0 => writeln!(
+                out,
+                "Killed by {} from {} ({} damage, {} overkill).\n",
+                hit.name,
+                pull.actors
+                    .get(&hit.source)
+                    .map_or("the environment", |actor| &actor.name),
+                hit.amount,
+                hit.overkill
+            )?,
+            Some(hit) => writeln!(
+                out,
+                "Killed by {} from {} ({} damage).\n",
+                hit.name,
+                pull.actors
+                    .get(&hit.source)
+                    .map_or("the environment", |actor| &actor.name),
+                hit.amount
+            )?,
+            None => writeln!(out, "No killing blow found.\n")?,
+        }
+
+        let image = format!("{}_{}.png", stem, i + 1);
+        render_death_snapshot(
+            &source,
+            recap,
+            window * 1000.0,
+            image_size,
+            output.with_file_name(&image),
+        )?;
+        writeln!(out, "![{} at {}]({})\n", recap.name, time, image)?;
+
+        writeln!(
+            out,
+            "HP over the last {}s: `{}`  ",
+            window,
+            recap.hp_sparkline(window)
+        )?;
+        writeln!(
+            out,
+            "Moved {:.1} yalms over the last {}s.\n",
+            recap.distance_moved(),
+            window
+        )?;
+
+        writeln!(out, "| Time | Event | Ability | Amount | From | HP |")?;
+        writeln!(out, "|---|---|---|--:|---|--:|")?;
+        for event in &recap.events {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                format_fight_time(event.time),
+                event.kind.text(),
+                markdown_cell(&event.ability),
+                event
+                    .amount
+                    .map(|amount| amount.to_string())
+                    .unwrap_or_default(),
+                markdown_cell(&event.source),
+                event
+                    .hp
+                    .map(|(hp, max)| format!("{}/{}", hp, max))
+                    .unwrap_or_default()
+            )?;
+        }
+        writeln!(out)?;
+    }
+
+    std::fs::write(output, out)?;
+    println!("Recapped {} deaths in {}", recaps.len(), output.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        positions::Interpolation,
+        report::test_util::{actor, pull_with},
+    };
+
+    use super::{death_recaps, RecapKind};
+
+    #[test]
+    fn death_recap_test() {
+        let hp = |hit_points| serde_json::json!({"hitPoints": hit_points, "maxHitPoints": 100000, "mp": 10000, "x": 0, "y": 0, "facing": 0});
+        let mut pull = pull_with(
+            [
+                (1, actor("Player 1", "Player", "Paladin")),
+                (2, actor("Player 2", "Player", "WhiteMage")),
+                (100, actor("Hephaistos", "NPC", "Boss")),
+            ],
+            serde_json::json!([
+                // Too long before the death to be included
+                {"type": "damage", "abilityGameID": 31000, "amount": 5000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 1000, "targetResources": hp(95000)},
+                {"type": "applydebuff", "abilityGameID": 1002000, "duration": 10000, "sourceID": 100, "targetID": 1, "timestamp": 10000},
+                {"type": "damage", "abilityGameID": 31000, "amount": 60000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 12000, "targetResources": hp(40000)},
+                {"type": "heal", "abilityGameID": 7, "amount": 10000, "hitType": 1, "sourceID": 2, "targetID": 1, "timestamp": 13000, "targetResources": hp(50000)},
+                {"type": "damage", "abilityGameID": 31001, "amount": 60000, "overkill": 10000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 14000, "targetResources": hp(0)},
+                {"type": "death", "sourceID": 100, "targetID": 1, "timestamp": 14001},
+                {"type": "death", "sourceID": 1, "targetID": 100, "timestamp": 14500}
+            ]),
+        );
+        pull.fight.end_time = 20000.0;
+
+        let recaps = death_recaps(&pull, 5.0, Interpolation::Linear);
+        assert_eq!(recaps.len(), 1);
+        let recap = &recaps[0];
+        assert_eq!(recap.name, "Player 1");
+        assert_eq!(recap.killing_blow.as_ref().unwrap().ability_id, 31001);
+        assert_eq!(
+            recap
+                .events
+                .iter()
+                .map(|event| (event.time, event.kind, event.source.as_str(), event.hp))
+                .collect::<Vec<_>>(),
+            vec![
+                (10000.0, RecapKind::Gained, "Hephaistos", None),
+                (
+                    12000.0,
+                    RecapKind::Damage,
+                    "Hephaistos",
+                    Some((40000, 100000))
+                ),
+                (13000.0, RecapKind::Heal, "Player 2", Some((50000, 100000))),
+                (14000.0, RecapKind::Damage, "Hephaistos", Some((0, 100000))),
+            ]
+        );
+        assert_eq!(recap.hp_sparkline(5.0), "  ▄▅▁");
+    }
+}
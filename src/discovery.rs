@@ -0,0 +1,191 @@
+use std::{
+    error::Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{client::Client, queries};
+
+/// Whose reports to look through.
+#[derive(Debug, Clone)]
+pub enum ReportOwner {
+    Guild {
+        name: String,
+        server: String,
+        region: String,
+    },
+    GuildId(i64),
+    User(i64),
+}
+
+/// A boss pull found in one of the discovered reports.
+#[derive(Debug, Clone)]
+pub struct FoundFight {
+    pub code: String,
+    pub report_title: String,
+    pub fight_id: i64,
+    pub name: String,
+    pub kill: bool,
+}
+
+/// Parses a date like "2024-05-01" or "2024-05-01 20:00", or a time span like "7days" that counts
+/// back from now.
+pub fn parse_date(text: &str) -> Result<SystemTime, String> {
+    if let Ok(ago) = humantime::parse_duration(text) {
+        return Ok(SystemTime::now() - ago);
+    }
+
+    let text = text.trim();
+    let full = match text.len() {
+        // Just a date, or a date with hours and minutes
+        10 => format!("{} 00:00:00", text),
+        16 => format!("{}:00", text),
+        _ => text.to_string(),
+    };
+    humantime::parse_rfc3339_weak(&full)
+        .map_err(|e| format!("bad date {:?} (use YYYY-MM-DD or e.g. 7days): {}", text, e))
+}
+
+fn unix_millis(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as f64
+}
+
+/// Pages through every report the owner uploaded between `since` and `until`, returning the boss
+/// pulls in them, oldest report first. Trash fights are skipped, and with an `encounter_id` so is
+/// every other boss.
+pub async fn find_fights(
+    client: &Client,
+    owner: &ReportOwner,
+    since: SystemTime,
+    until: SystemTime,
+    zone_id: Option<i64>,
+    encounter_id: Option<i64>,
+) -> Result<Vec<FoundFight>, Box<dyn Error>> {
+    let (guild_id, user_id) = match owner {
+        // Looked up first, so a misspelled guild is an error rather than an empty result
+        ReportOwner::Guild {
+            name,
+            server,
+            region,
+        } => (Some(find_guild(client, name, server, region).await?), None),
+        ReportOwner::GuildId(id) => (Some(*id), None),
+        ReportOwner::User(id) => (None, Some(*id)),
+    };
+
+    let mut reports = Vec::new();
+    for page in 1.. {
+        let response = client
+            .query::<queries::Reports>(queries::reports::Variables {
+                guild_id,
+                guild_name: None,
+                guild_server: None,
+                guild_region: None,
+                user_id,
+                start_time: Some(unix_millis(since)),
+                end_time: Some(unix_millis(until)),
+                zone_id,
+                encounter_id,
+                page,
+            })
+            .await?
+            .report_data
+            .and_then(|report_data| report_data.reports)
+            .ok_or("no report data in response")?;
+
+        reports.extend(response.data.into_iter().flatten().flatten());
+        if !response.has_more_pages {
+            break;
+        }
+    }
+    reports.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+    let mut fights = Vec::new();
+    for report in reports {
+        for fight in report.fights.into_iter().flatten().flatten() {
+            if fight.encounter_id == 0 {
+                continue;
+            }
+
+            fights.push(FoundFight {
+                code: report.code.clone(),
+                report_title: report.title.clone(),
+                fight_id: fight.id,
+                name: fight.name,
+                kill: fight.kill.unwrap_or(false),
+            });
+        }
+    }
+
+    Ok(fights)
+}
+
+/// The ID of the guild called `name` on the given server.
+pub async fn find_guild(
+    client: &Client,
+    name: &str,
+    server: &str,
+    region: &str,
+) -> Result<i64, Box<dyn Error>> {
+    let guild = client
+        .query::<queries::Guild>(queries::guild::Variables {
+            name: name.to_string(),
+            server_slug: server.to_string(),
+            server_region: region.to_string(),
+        })
+        .await?
+        .guild_data
+        .and_then(|guild_data| guild_data.guild)
+        .ok_or_else(|| format!("no guild named {:?} on {} ({})", name, server, region))?;
+    Ok(guild.id)
+}
+
+/// One page of the guilds on a server, as (ID, name), and whether there are more pages.
+pub async fn list_guilds(
+    client: &Client,
+    server: &str,
+    region: &str,
+    page: i64,
+) -> Result<(Vec<(i64, String)>, bool), Box<dyn Error>> {
+    let guilds = client
+        .query::<queries::Guilds>(queries::guilds::Variables {
+            page,
+            server_slug: Some(server.to_string()),
+            server_region: Some(region.to_string()),
+        })
+        .await?
+        .guild_data
+        .and_then(|guild_data| guild_data.guilds)
+        .ok_or("no guild data in response")?;
+
+    let list = guilds
+        .data
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|guild| (guild.id, guild.name))
+        .collect();
+    Ok((list, guilds.has_more_pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::parse_date;
+
+    #[test]
+    fn parse_date_test() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        assert_eq!(parse_date("2024-05-01"), Ok(at(1714521600)));
+        assert_eq!(parse_date("2024-05-01 20:30"), Ok(at(1714595400)));
+        assert_eq!(parse_date("2024-05-01T20:30:15Z"), Ok(at(1714595415)));
+
+        let week_ago = parse_date("7days").unwrap();
+        let expected = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+        assert!(expected.duration_since(week_ago).unwrap_or_default() < Duration::from_secs(5));
+
+        assert!(parse_date("last tuesday").is_err());
+    }
+}
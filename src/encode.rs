@@ -0,0 +1,326 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use cairo::ImageSurface;
+
+/// Container the rendered frames end up in, picked from the output file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Needs an `ffmpeg` binary on PATH
+    Mp4,
+    Gif,
+    Apng,
+    Webp,
+}
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("mp4") => Ok(OutputFormat::Mp4),
+            Some("gif") => Ok(OutputFormat::Gif),
+            Some("png") | Some("apng") => Ok(OutputFormat::Apng),
+            Some("webp") => Ok(OutputFormat::Webp),
+            _ => Err(format!(
+                "can't tell the output format of {}; use .mp4, .gif, .png/.apng or .webp",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Receives rendered frames one at a time and writes them out in some format.
+pub trait FrameEncoder {
+    fn add_frame(&mut self, surface: &mut ImageSurface) -> Result<(), Box<dyn Error>>;
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>>;
+}
+
+/// Sets up an encoder for `output`. `frame_count` has to be known up front for APNG, and MP4 frames
+/// are staged as PNG files in a temporary directory before being handed to ffmpeg. Asking for zero
+/// frames is an error.
+pub fn create_encoder(
+    output: &Path,
+    (width, height): (i32, i32),
+    frame_count: usize,
+    framerate: u32,
+) -> Result<Box<dyn FrameEncoder>, Box<dyn Error>> {
+    let format = OutputFormat::from_path(output)?;
+
+    // None of the formats can hold an empty animation (APNG can't even declare one)
+    if frame_count == 0 {
+        return Err(format!("there are no frames to write to {}", output.display()).into());
+    }
+
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    Ok(match format {
+        OutputFormat::Mp4 => {
+            let frames_dir = FramesDir::create()?;
+            Box::new(Mp4Encoder {
+                frames_dir,
+                output: std::env::current_dir()?.join(output),
+                framerate,
+                frame_idx: 0,
+            })
+        }
+        OutputFormat::Gif => {
+            let mut encoder = gif::Encoder::new(
+                BufWriter::new(File::create(output)?),
+                width.try_into()?,
+                height.try_into()?,
+                &[],
+            )?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            Box::new(GifEncoder {
+                encoder,
+                // GIF delays are in hundredths of a second
+                delay: (100.0 / framerate as f64).round() as u16,
+            })
+        }
+        OutputFormat::Apng => {
+            let mut encoder = png::Encoder::new(
+                BufWriter::new(File::create(output)?),
+                width.try_into()?,
+                height.try_into()?,
+            );
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frame_count.try_into()?, 0)?;
+            encoder.set_frame_delay(1, framerate.try_into()?)?;
+            Box::new(ApngEncoder {
+                writer: encoder.write_header()?,
+            })
+        }
+        OutputFormat::Webp => Box::new(WebpEncoder {
+            encoder: webp_animation::Encoder::new((width.try_into()?, height.try_into()?))
+                .map_err(|e| format!("{:?}", e))?,
+            output: output.to_path_buf(),
+            frame_duration: 1000.0 / framerate as f64,
+            frame_idx: 0,
+        }),
+    })
+}
+
+// Cairo stores RGB24 pixels as native-endian 0x00RRGGBB words, with rows padded to the stride.
+fn surface_to_rgba(surface: &mut ImageSurface) -> Result<Vec<u8>, Box<dyn Error>> {
+    surface.flush();
+
+    let width = surface.width() as usize;
+    let height = surface.height() as usize;
+    let stride = surface.stride() as usize;
+    let data = surface.data()?;
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in data.chunks(stride).take(height) {
+        for pixel in row[..width * 4].chunks_exact(4) {
+            let [_, r, g, b] = u32::from_ne_bytes(pixel.try_into().unwrap()).to_be_bytes();
+            rgba.extend([r, g, b, 0xff]);
+        }
+    }
+
+    Ok(rgba)
+}
+
+// Scratch directory for MP4 frames, removed again once the encoder is done with it (or gives up).
+struct FramesDir(PathBuf);
+impl FramesDir {
+    fn create() -> Result<Self, Box<dyn Error>> {
+        // Several renders can be in flight at once when serving.
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "ff_mechanic_viz_frames_{}_{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(FramesDir(dir))
+    }
+}
+impl Drop for FramesDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+struct Mp4Encoder {
+    frames_dir: FramesDir,
+    output: PathBuf,
+    framerate: u32,
+    frame_idx: usize,
+}
+impl FrameEncoder for Mp4Encoder {
+    fn add_frame(&mut self, surface: &mut ImageSurface) -> Result<(), Box<dyn Error>> {
+        let frame_filename = self.frames_dir.0.join(format!("{:04}.png", self.frame_idx));
+        let mut f = BufWriter::new(File::create(frame_filename)?);
+        surface.write_to_png(&mut f)?;
+        self.frame_idx += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        concat_images_to_video(&self.frames_dir.0, &self.output, self.framerate)
+    }
+}
+
+fn concat_images_to_video(
+    dir: impl AsRef<Path>,
+    out_vid: impl AsRef<Path>,
+    target_framerate: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = std::process::Command::new("ffmpeg");
+    cmd.current_dir(dir.as_ref());
+    cmd.arg("-y");
+    cmd.arg("-f").arg("image2");
+    cmd.arg("-r").arg(target_framerate.to_string());
+    cmd.arg("-i").arg("%04d.png");
+    // yuv420p needs even dimensions, so pad odd sizes by a pixel.
+    cmd.arg("-vf").arg("pad=ceil(iw/2)*2:ceil(ih/2)*2");
+    cmd.arg("-c:v").arg("libx264");
+    cmd.arg("-pix_fmt").arg("yuv420p");
+    cmd.arg(out_vid.as_ref());
+
+    let status = cmd
+        .status()
+        .map_err(|e| format!("couldn't run ffmpeg (needed for .mp4 output): {}", e))?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed with {}", status).into());
+    }
+
+    Ok(())
+}
+
+struct GifEncoder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    delay: u16,
+}
+impl FrameEncoder for GifEncoder {
+    fn add_frame(&mut self, surface: &mut ImageSurface) -> Result<(), Box<dyn Error>> {
+        let mut rgba = surface_to_rgba(surface)?;
+        // Quantizes each frame to its own 256 color palette; speed 10 is the usual size/quality
+        // tradeoff and the frames only have a handful of colors anyway.
+        let mut frame = gif::Frame::from_rgba_speed(
+            surface.width().try_into()?,
+            surface.height().try_into()?,
+            &mut rgba,
+            10,
+        );
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        // The trailer is written when the encoder is dropped.
+        drop(self.encoder);
+        Ok(())
+    }
+}
+
+struct ApngEncoder {
+    writer: png::Writer<BufWriter<File>>,
+}
+impl FrameEncoder for ApngEncoder {
+    fn add_frame(&mut self, surface: &mut ImageSurface) -> Result<(), Box<dyn Error>> {
+        self.writer.write_image_data(&surface_to_rgba(surface)?)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+struct WebpEncoder {
+    encoder: webp_animation::Encoder,
+    output: PathBuf,
+    frame_duration: f64,
+    frame_idx: usize,
+}
+impl WebpEncoder {
+    fn timestamp(&self) -> i32 {
+        (self.frame_idx as f64 * self.frame_duration) as i32
+    }
+}
+impl FrameEncoder for WebpEncoder {
+    fn add_frame(&mut self, surface: &mut ImageSurface) -> Result<(), Box<dyn Error>> {
+        let rgba = surface_to_rgba(surface)?;
+        self.encoder
+            .add_frame(&rgba, self.timestamp())
+            .map_err(|e| format!("{:?}", e))?;
+        self.frame_idx += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        let end = self.timestamp();
+        let data = self.encoder.finalize(end).map_err(|e| format!("{:?}", e))?;
+        std::fs::write(&self.output, &*data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use cairo::{Context, Format, ImageSurface};
+
+    use super::{create_encoder, surface_to_rgba, OutputFormat};
+
+    #[test]
+    fn output_format_test() {
+        let format = |path: &str| OutputFormat::from_path(Path::new(path)).ok();
+
+        assert_eq!(format("out/fight.mp4"), Some(OutputFormat::Mp4));
+        assert_eq!(format("fight.GIF"), Some(OutputFormat::Gif));
+        assert_eq!(format("fight.png"), Some(OutputFormat::Apng));
+        assert_eq!(format("fight.apng"), Some(OutputFormat::Apng));
+        assert_eq!(format("fight.webp"), Some(OutputFormat::Webp));
+        assert_eq!(format("fight.avi"), None);
+        assert_eq!(format("fight"), None);
+    }
+
+    #[test]
+    fn create_encoder_empty_test() {
+        let dir =
+            std::env::temp_dir().join(format!("ff_mechanic_viz_empty_{}", std::process::id()));
+        for name in ["fight.mp4", "fight.gif", "fight.png", "fight.webp"] {
+            let output = dir.join(name);
+            assert!(create_encoder(&output, (4, 4), 0, 30).is_err());
+            assert!(!output.exists());
+        }
+    }
+
+    #[test]
+    fn surface_to_rgba_test() {
+        let mut surface = ImageSurface::create(Format::Rgb24, 3, 2).unwrap();
+        {
+            let ctx = Context::new(&surface).unwrap();
+            ctx.set_source_rgb(1.0, 0.0, 0.0);
+            ctx.rectangle(0.0, 0.0, 1.0, 1.0);
+            ctx.fill().unwrap();
+            ctx.set_source_rgb(0.0, 0.0, 1.0);
+            ctx.rectangle(2.0, 1.0, 1.0, 1.0);
+            ctx.fill().unwrap();
+        }
+
+        let rgba = surface_to_rgba(&mut surface).unwrap();
+        assert_eq!(rgba.len(), 3 * 2 * 4);
+        assert_eq!(&rgba[0..4], &[255, 0, 0, 255]);
+        assert_eq!(&rgba[4..8], &[0, 0, 0, 255]);
+        assert_eq!(&rgba[20..24], &[0, 0, 255, 255]);
+    }
+}
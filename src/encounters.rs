@@ -0,0 +1,373 @@
+use std::{
+    error::Error,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{client::Client, queries};
+
+// The catalog only changes when new content comes out, so a week old copy is fine.
+const CATALOG_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+enum AliasTarget {
+    Id(i64),
+    Name(&'static str),
+}
+
+// Short names people actually type. Encounters not listed here can still be found by (part of)
+// their full name.
+const ALIASES: &[(&str, AliasTarget)] = &[
+    ("p5s", AliasTarget::Id(83)),
+    ("p6s", AliasTarget::Id(84)),
+    ("p7s", AliasTarget::Id(85)),
+    ("p8s1", AliasTarget::Id(86)),
+    ("p8s2", AliasTarget::Id(87)),
+    ("ucob", AliasTarget::Name("The Unending Coil of Bahamut")),
+    ("uwu", AliasTarget::Name("The Weapon's Refrain")),
+    ("tea", AliasTarget::Name("The Epic of Alexander")),
+    ("dsr", AliasTarget::Name("Dragonsong's Reprise")),
+    ("top", AliasTarget::Name("The Omega Protocol")),
+];
+
+/// An encounter as given on the command line: an ID, an alias like "p8s2" or "TOP", or a name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncounterSpec {
+    Id(i64),
+    Name(String),
+}
+
+pub fn parse_encounter_spec(spec: &str) -> Result<EncounterSpec, String> {
+    let spec = spec.trim();
+    if spec.is_empty() {
+        return Err("empty encounter".to_string());
+    }
+
+    Ok(match spec.parse() {
+        Ok(id) => EncounterSpec::Id(id),
+        Err(_) => EncounterSpec::Name(spec.to_string()),
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEncounter {
+    pub id: i64,
+    pub name: String,
+    pub zone: String,
+    pub expansion: String,
+}
+
+/// Every encounter FF Logs knows about, by expansion and zone.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncounterCatalog {
+    pub encounters: Vec<CatalogEncounter>,
+}
+impl EncounterCatalog {
+    fn find(&self, name: &str) -> Result<&CatalogEncounter, String> {
+        let name = name.to_lowercase();
+        if let Some(encounter) = self
+            .encounters
+            .iter()
+            .find(|encounter| encounter.name.to_lowercase() == name)
+        {
+            return Ok(encounter);
+        }
+
+        let matches = self
+            .encounters
+            .iter()
+            .filter(|encounter| encounter.name.to_lowercase().contains(&name))
+            .collect::<Vec<_>>();
+        match matches.as_slice() {
+            [encounter] => Ok(encounter),
+            [] => Err(format!("no encounter matches {:?}", name)),
+            _ => Err(format!(
+                "{:?} matches several encounters: {}",
+                name,
+                matches
+                    .iter()
+                    .map(|encounter| format!(
+                        "{} ({}, {})",
+                        encounter.name, encounter.zone, encounter.id
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// Looks up an encounter name or alias.
+    pub fn resolve(&self, spec: &EncounterSpec) -> Result<i64, String> {
+        let name = match spec {
+            EncounterSpec::Id(id) => return Ok(*id),
+            EncounterSpec::Name(name) => name,
+        };
+
+        match alias(name) {
+            Some(AliasTarget::Id(id)) => Ok(*id),
+            Some(AliasTarget::Name(full_name)) => Ok(self.find(full_name)?.id),
+            None => Ok(self.find(name)?.id),
+        }
+    }
+}
+
+fn alias(name: &str) -> Option<&'static AliasTarget> {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map(|(_, target)| target)
+}
+
+async fn fetch_catalog(client: &Client) -> Result<EncounterCatalog, Box<dyn Error>> {
+    let expansions = client
+        .query::<queries::Encounters>(queries::encounters::Variables {})
+        .await?
+        .world_data
+        .and_then(|world_data| world_data.expansions)
+        .unwrap_or_default();
+
+    let mut encounters = Vec::new();
+    for expansion in expansions.into_iter().flatten() {
+        for zone in expansion.zones.into_iter().flatten().flatten() {
+            for encounter in zone.encounters.into_iter().flatten().flatten() {
+                encounters.push(CatalogEncounter {
+                    id: encounter.id,
+                    name: encounter.name,
+                    zone: zone.name.clone(),
+                    expansion: expansion.name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(EncounterCatalog { encounters })
+}
+
+/// Reads the catalog from `path`, fetching it again if it's missing or stale.
+pub async fn load_catalog(
+    client: &Client,
+    path: &Path,
+) -> Result<EncounterCatalog, Box<dyn Error>> {
+    let fresh = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < CATALOG_MAX_AGE);
+    if fresh {
+        if let Ok(catalog) = serde_json::from_slice(&std::fs::read(path)?) {
+            return Ok(catalog);
+        }
+    }
+
+    println!("Fetching encounter catalog...");
+    let catalog = fetch_catalog(client).await?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec(&catalog)?)?;
+
+    Ok(catalog)
+}
+
+/// Turns an encounter spec into an ID, only loading the catalog when a name has to be looked up.
+pub async fn resolve_encounter(
+    client: &Client,
+    catalog_path: &Path,
+    spec: &EncounterSpec,
+) -> Result<i64, Box<dyn Error>> {
+    match spec {
+        EncounterSpec::Id(id) => Ok(*id),
+        EncounterSpec::Name(name) => match alias(name) {
+            Some(AliasTarget::Id(id)) => Ok(*id),
+            _ => Ok(load_catalog(client, catalog_path).await?.resolve(spec)?),
+        },
+    }
+}
+
+/// A zone and the encounters in it.
+#[derive(Debug, Clone)]
+pub struct ZoneInfo {
+    pub id: i64,
+    pub name: String,
+    pub expansion: String,
+    // No longer receiving new rankings
+    pub frozen: bool,
+    pub encounters: Vec<(i64, String)>,
+}
+
+/// Every zone FF Logs knows about, or only those of one expansion.
+pub async fn list_zones(
+    client: &Client,
+    expansion_id: Option<i64>,
+) -> Result<Vec<ZoneInfo>, Box<dyn Error>> {
+    let zones = client
+        .query::<queries::Zones>(queries::zones::Variables { expansion_id })
+        .await?
+        .world_data
+        .and_then(|world_data| world_data.zones)
+        .unwrap_or_default();
+
+    Ok(zones
+        .into_iter()
+        .flatten()
+        .map(|zone| ZoneInfo {
+            id: zone.id,
+            name: zone.name,
+            expansion: zone.expansion.name,
+            frozen: zone.frozen,
+            encounters: zone
+                .encounters
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|encounter| (encounter.id, encounter.name))
+                .collect(),
+        })
+        .collect())
+}
+
+/// A kill from an encounter's speed rankings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedKill {
+    pub code: String,
+    pub fight_id: i64,
+    // Milliseconds
+    pub duration: f64,
+    // The guild or group that got the kill
+    pub name: String,
+}
+
+// Picks the kills out of one page of `fightRankings` JSON, along with whether there are more pages.
+fn get_ranked_kills(fight_rankings: &Value) -> Option<(Vec<RankedKill>, bool)> {
+    let mut result = Vec::new();
+
+    for rank in fight_rankings.get("rankings")?.as_array()? {
+        let report = rank.get("report")?;
+        result.push(RankedKill {
+            code: report.get("code")?.as_str()?.to_string(),
+            fight_id: report.get("fightID")?.as_i64()?,
+            duration: rank.get("duration").and_then(Value::as_f64).unwrap_or(0.0),
+            name: rank
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+
+    let has_more_pages = fight_rankings
+        .get("hasMorePages")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    Some((result, has_more_pages))
+}
+
+/// The encounter's name and up to `limit` of its fastest kills, fastest first.
+pub async fn load_ranked_kills(
+    client: &Client,
+    encounter_id: i64,
+    limit: usize,
+) -> Result<(String, Vec<RankedKill>), Box<dyn Error>> {
+    let mut name = String::new();
+    let mut kills = Vec::new();
+    for page in 1.. {
+        let encounter = client
+            .query::<queries::FightRankings>(queries::fight_rankings::Variables {
+                encounter_id,
+                page,
+            })
+            .await?
+            .world_data
+            .and_then(|world_data| world_data.encounter)
+            .ok_or_else(|| format!("no encounter {}", encounter_id))?;
+        name = encounter.name;
+
+        let (page_kills, has_more_pages) = encounter
+            .fight_rankings
+            .as_ref()
+            .and_then(get_ranked_kills)
+            .ok_or_else(|| format!("no rankings for encounter {}", encounter_id))?;
+        kills.extend(page_kills);
+        if kills.len() >= limit || !has_more_pages {
+            break;
+        }
+    }
+
+    kills.truncate(limit);
+    Ok((name, kills))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_ranked_kills, parse_encounter_spec, CatalogEncounter, EncounterCatalog, EncounterSpec,
+        RankedKill,
+    };
+
+    #[test]
+    fn resolve_encounter_test() {
+        let encounter = |id, name: &str| CatalogEncounter {
+            id,
+            name: name.to_string(),
+            zone: "Zone".to_string(),
+            expansion: "Endwalker".to_string(),
+        };
+        let catalog = EncounterCatalog {
+            encounters: vec![
+                encounter(86, "Hephaistos"),
+                encounter(87, "Hephaistos II"),
+                encounter(1068, "The Omega Protocol"),
+                encounter(1065, "Dragonsong's Reprise"),
+            ],
+        };
+        let resolve = |spec: &str| catalog.resolve(&parse_encounter_spec(spec).unwrap());
+
+        assert_eq!(parse_encounter_spec(" 83 "), Ok(EncounterSpec::Id(83)));
+        assert_eq!(resolve("83"), Ok(83));
+        assert_eq!(resolve("P8S2"), Ok(87));
+        assert_eq!(resolve("TOP"), Ok(1068));
+        assert_eq!(resolve("hephaistos"), Ok(86));
+        assert_eq!(resolve("dragonsong"), Ok(1065));
+        assert!(resolve("heph").unwrap_err().contains("several"));
+        assert!(resolve("Zeromus").is_err());
+        assert!(parse_encounter_spec("").is_err());
+    }
+
+    #[test]
+    fn ranked_kills_test() {
+        let rankings = serde_json::json!({
+            "page": 1,
+            "hasMorePages": true,
+            "count": 2,
+            "rankings": [
+                {"name": "Fast Group", "duration": 612345, "report": {"code": "aaa111", "fightID": 7, "startTime": 0}},
+                {"duration": 620000, "report": {"code": "bbb222", "fightID": 3, "startTime": 0}}
+            ]
+        });
+
+        let kill = |code: &str, fight_id, duration, name: &str| RankedKill {
+            code: code.to_string(),
+            fight_id,
+            duration,
+            name: name.to_string(),
+        };
+        assert_eq!(
+            get_ranked_kills(&rankings),
+            Some((
+                vec![
+                    kill("aaa111", 7, 612345.0, "Fast Group"),
+                    kill("bbb222", 3, 620000.0, ""),
+                ],
+                true
+            ))
+        );
+
+        assert_eq!(
+            get_ranked_kills(&serde_json::json!({"rankings": []})),
+            Some((vec![], false))
+        );
+        assert_eq!(get_ranked_kills(&serde_json::json!({})), None);
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct CombatantStats {
     pub attack: i64,
     #[serde(rename = "attackMagicPotency")]
@@ -26,7 +26,7 @@ pub struct CombatantStats {
     pub vitality: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Resources {
     pub absorb: Option<i64>,
     pub facing: i64, // to get radians: divide by 1000, multiply by pi
@@ -38,8 +38,13 @@ pub struct Resources {
     pub x: i64,
     pub y: i64,
 }
+impl Resources {
+    pub fn facing_radians(&self) -> f64 {
+        self.facing as f64 / 1000.0 * std::f64::consts::PI
+    }
+}
 
-#[derive(Serialize, Debug, Deserialize)]
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
 pub struct Aura {
     pub ability: i64,
     pub name: String,
@@ -47,27 +52,45 @@ pub struct Aura {
     pub stacks: i64,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
 pub struct SourceInfo {
     #[serde(rename = "sourceID")]
-    id: i64,
+    pub id: i64,
     #[serde(rename = "sourceMarker")]
     marker: Option<i64>,
     #[serde(rename = "sourceInstance")]
     instance: Option<i64>,
 }
+impl SourceInfo {
+    pub fn new(id: i64) -> Self {
+        SourceInfo {
+            id,
+            marker: None,
+            instance: None,
+        }
+    }
+}
 
-#[derive(Serialize, Debug, Deserialize)]
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
 pub struct TargetInfo {
     #[serde(rename = "targetID")]
-    id: i64,
+    pub id: i64,
     #[serde(rename = "targetMarker")]
     marker: Option<i64>,
     #[serde(rename = "targetInstance")]
     instance: Option<i64>,
 }
+impl TargetInfo {
+    pub fn new(id: i64) -> Self {
+        TargetInfo {
+            id,
+            marker: None,
+            instance: None,
+        }
+    }
+}
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, PartialEq)]
 #[serde(tag = "type")]
 pub enum Event {
     #[serde(rename = "absorbed")]
@@ -248,6 +271,8 @@ pub enum Event {
         #[serde(rename = "unmitigatedAmount")]
         unmitigated_amount: Option<i64>,
         multiplier: Option<f64>,
+        // Damage past what it took to kill the target
+        overkill: Option<i64>,
 
         #[serde(rename = "directHit")]
         direct_hit: Option<bool>,
@@ -293,6 +318,9 @@ pub enum Event {
 
     #[serde(rename = "headmarker")]
     HeadMarker {
+        #[serde(rename = "markerID")]
+        marker_id: Option<i64>,
+
         #[serde(flatten)]
         source: SourceInfo,
         #[serde(flatten)]
@@ -468,6 +496,35 @@ impl Event {
         }
     }
 
+    // (source, target) actor IDs, for the events that have them
+    pub fn get_actor_ids(&self) -> (Option<i64>, Option<i64>) {
+        match self {
+            Event::Absorbed { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::ApplyBuff { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::ApplyBuffStack { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::ApplyDebuff { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::BeginCast { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::CalculatedDamage { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::CalculatedHeal { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::Cast { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::CombatantInfo { source, .. } => (Some(source.id), None),
+            Event::Damage { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::Death { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::EncounterEnd { .. } => (None, None),
+            Event::GaugeUpdate { .. } => (None, None),
+            Event::Heal { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::HeadMarker { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::LimitBreakUpdate { .. } => (None, None),
+            Event::RefreshBuff { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::RefreshDebuff { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::RemoveBuff { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::RemoveBuffStack { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::RemoveDebuff { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::TargetabilityUpdate { source, target, .. } => (Some(source.id), Some(target.id)),
+            Event::Tether { source, target, .. } => (Some(source.id), Some(target.id)),
+        }
+    }
+
     pub fn get_source_resources(&self) -> Option<(i64, &Resources)> {
         match self {
             Event::Absorbed { .. } => None,
@@ -0,0 +1,517 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt::Write,
+    path::Path,
+};
+
+use serde_json::Value;
+
+use crate::{positions::Interpolation, report::Pull};
+
+/// File format of an exported table, picked from the output file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+    // Only available when built with the `parquet` feature
+    Parquet,
+}
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("csv") => Ok(ExportFormat::Csv),
+            Some("jsonl") => Ok(ExportFormat::JsonLines),
+            Some("parquet") => Ok(ExportFormat::Parquet),
+            _ => Err(format!(
+                "can't tell the export format of {}; use .csv, .jsonl or .parquet",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    Float,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Null,
+}
+impl Cell {
+    fn to_json(&self) -> Value {
+        match self {
+            Cell::Int(value) => Value::from(*value),
+            Cell::Float(value) => Value::from(*value),
+            Cell::Text(value) => Value::from(value.as_str()),
+            Cell::Null => Value::Null,
+        }
+    }
+}
+
+/// Quotes a CSV field if it needs it.
+pub fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Rows of typed cells, one cell per column.
+#[derive(Debug)]
+pub struct Table {
+    pub columns: Vec<(&'static str, ColumnType)>,
+    pub rows: Vec<Vec<Cell>>,
+}
+impl Table {
+    pub fn to_csv(&self) -> String {
+        let mut out = self
+            .columns
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(",");
+        out.push('\n');
+
+        for row in &self.rows {
+            let cells = row
+                .iter()
+                .map(|cell| match cell {
+                    Cell::Int(value) => value.to_string(),
+                    Cell::Float(value) => value.to_string(),
+                    Cell::Text(value) => csv_field(value),
+                    Cell::Null => String::new(),
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", cells.join(",")).unwrap();
+        }
+
+        out
+    }
+
+    /// One JSON object per line, keyed by column name.
+    pub fn to_json_lines(&self) -> String {
+        let mut out = String::new();
+        for row in &self.rows {
+            // Written by hand to keep the keys in column order
+            let fields = self
+                .columns
+                .iter()
+                .zip(row)
+                .map(|((name, _), cell)| format!("{}:{}", Value::from(*name), cell.to_json()))
+                .collect::<Vec<_>>();
+            writeln!(out, "{{{}}}", fields.join(",")).unwrap();
+        }
+
+        out
+    }
+
+    #[cfg(feature = "parquet")]
+    fn write_parquet(&self, output: &Path) -> Result<(), Box<dyn Error>> {
+        use std::sync::Arc;
+
+        use parquet::{
+            data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+            file::{properties::WriterProperties, writer::SerializedFileWriter},
+            schema::parser::parse_message_type,
+        };
+
+        let fields = self
+            .columns
+            .iter()
+            .map(|(name, column_type)| match column_type {
+                ColumnType::Int => format!("OPTIONAL INT64 {};", name),
+                ColumnType::Float => format!("OPTIONAL DOUBLE {};", name),
+                ColumnType::Text => format!("OPTIONAL BYTE_ARRAY {} (UTF8);", name),
+            })
+            .collect::<Vec<_>>();
+        let schema = parse_message_type(&format!("message export {{ {} }}", fields.join(" ")))?;
+
+        let mut writer = SerializedFileWriter::new(
+            std::fs::File::create(output)?,
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )?;
+        let mut row_group = writer.next_row_group()?;
+        for (i, (_, column_type)) in self.columns.iter().enumerate() {
+            let cells = self.rows.iter().map(|row| &row[i]);
+            let levels = cells
+                .clone()
+                .map(|cell| i16::from(*cell != Cell::Null))
+                .collect::<Vec<_>>();
+
+            let mut column = row_group
+                .next_column()?
+                .ok_or("ran out of parquet columns")?;
+            match column_type {
+                ColumnType::Int => {
+                    let values = cells
+                        .filter_map(|cell| match cell {
+                            Cell::Int(value) => Some(*value),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    column
+                        .typed::<Int64Type>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                ColumnType::Float => {
+                    let values = cells
+                        .filter_map(|cell| match cell {
+                            Cell::Float(value) => Some(*value),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    column
+                        .typed::<DoubleType>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                ColumnType::Text => {
+                    let values = cells
+                        .filter_map(|cell| match cell {
+                            Cell::Text(value) => Some(ByteArray::from(value.as_str())),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+            }
+            column.close()?;
+        }
+        row_group.close()?;
+        writer.close()?;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn write_parquet(&self, _output: &Path) -> Result<(), Box<dyn Error>> {
+        Err("this build can't write parquet; rebuild with --features parquet".into())
+    }
+
+    /// Writes the table to `output` in the format its extension asks for.
+    pub fn write(&self, output: &Path) -> Result<(), Box<dyn Error>> {
+        let format = ExportFormat::from_path(output)?;
+        if let Some(dir) = output.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        match format {
+            ExportFormat::Csv => std::fs::write(output, self.to_csv())?,
+            ExportFormat::JsonLines => std::fs::write(output, self.to_json_lines())?,
+            ExportFormat::Parquet => self.write_parquet(output)?,
+        }
+        println!("Wrote {} rows to {}", self.rows.len(), output.display());
+
+        Ok(())
+    }
+}
+
+// Facing (in radians) and hit points of an actor, whenever an event says what they were
+#[derive(Debug, Clone, Copy)]
+struct ResourceSample {
+    facing: f64,
+    hit_points: i64,
+}
+
+/// Every actor's position, facing and HP, sampled `rate` times a second over the pull. Actors only
+/// get rows between the first and last time the log says anything about them. Timestamps are in
+/// milliseconds since the start of the pull; coordinates are FF Logs' (hundredths of a yalm).
+/// Positions between samples are filled in with `interpolation`.
+pub fn track_table(pull: &Pull, rate: f64, interpolation: Interpolation) -> Table {
+    let mut resources: HashMap<i64, BTreeMap<i64, ResourceSample>> = HashMap::new();
+    for event in &pull.events {
+        for (id, resource) in [event.get_source_resources(), event.get_target_resources()]
+            .into_iter()
+            .flatten()
+        {
+            resources.entry(id).or_default().insert(
+                event.get_timestamp(),
+                ResourceSample {
+                    facing: resource.facing_radians(),
+                    hit_points: resource.hit_points,
+                },
+            );
+        }
+    }
+
+    let step = 1000.0 / rate;
+    let mut ids = pull.positions.keys().copied().collect::<Vec<_>>();
+    ids.sort();
+
+    let mut rows = Vec::new();
+    let mut track = Vec::new();
+    for id in ids {
+        let (Some(history), Some(samples)) = (pull.positions.get(&id), resources.get(&id)) else {
+            continue;
+        };
+        let Some((first, last)) = history.time_bounds() else {
+            continue;
+        };
+        let (name, job) = pull
+            .actors
+            .get(&id)
+            .map_or(("", ""), |actor| (&actor.name, &actor.subtype));
+
+        let start = pull.fight.start_time.max(first);
+        let end = pull.fight.end_time.min(last);
+        if end < start {
+            continue;
+        }
+        track.resize(((end - start) / step).floor() as usize + 1, (0.0, 0.0));
+        history.resample_into(start, step, interpolation, &mut track);
+
+        for (i, &(x, y)) in track.iter().enumerate() {
+            let time = start + i as f64 * step;
+            let sample = samples
+                .range(..=time as i64)
+                .next_back()
+                .map(|(_, sample)| *sample);
+
+            rows.push(vec![
+                Cell::Float(time - pull.fight.start_time),
+                Cell::Int(id),
+                Cell::Text(name.to_string()),
+                Cell::Text(job.to_string()),
+                Cell::Float(x),
+                Cell::Float(y),
+                sample.map_or(Cell::Null, |sample| Cell::Float(sample.facing)),
+                sample.map_or(Cell::Null, |sample| Cell::Int(sample.hit_points)),
+            ]);
+        }
+    }
+
+    // Everyone at the same moment together, like the frames of a video
+    rows.sort_by(|a, b| match (&a[0], &b[0]) {
+        (Cell::Float(a), Cell::Float(b)) => a.total_cmp(b),
+        _ => std::cmp::Ordering::Equal,
+    });
+
+    Table {
+        columns: vec![
+            ("timestamp", ColumnType::Float),
+            ("actor_id", ColumnType::Int),
+            ("name", ColumnType::Text),
+            ("job", ColumnType::Text),
+            ("x", ColumnType::Float),
+            ("y", ColumnType::Float),
+            ("facing", ColumnType::Float),
+            ("hp", ColumnType::Int),
+        ],
+        rows,
+    }
+}
+
+/// Every event in the pull as one row, with the fields most event types share pulled out into
+/// columns and the whole event kept as JSON in the last one.
+pub fn event_table(pull: &Pull) -> Table {
+    let actor_name = |id: Option<i64>| match id.and_then(|id| pull.actors.get(&id)) {
+        Some(actor) => Cell::Text(actor.name.clone()),
+        None => Cell::Null,
+    };
+
+    let rows = pull
+        .events
+        .iter()
+        .map(|event| {
+            let json = serde_json::to_value(event).unwrap();
+            let int = |key: &str| json.get(key).and_then(Value::as_i64);
+            let ability_id = int("abilityGameID");
+
+            vec![
+                Cell::Float(event.get_timestamp() as f64 - pull.fight.start_time),
+                json.get("type")
+                    .and_then(Value::as_str)
+                    .map_or(Cell::Null, |kind| Cell::Text(kind.to_string())),
+                int("sourceID").map_or(Cell::Null, Cell::Int),
+                actor_name(int("sourceID")),
+                int("targetID").map_or(Cell::Null, Cell::Int),
+                actor_name(int("targetID")),
+                ability_id.map_or(Cell::Null, Cell::Int),
+                ability_id.map_or(Cell::Null, |id| Cell::Text(pull.abilities.name(id))),
+                int("amount").map_or(Cell::Null, Cell::Int),
+                Cell::Text(json.to_string()),
+            ]
+        })
+        .collect();
+
+    Table {
+        columns: vec![
+            ("timestamp", ColumnType::Float),
+            ("type", ColumnType::Text),
+            ("source_id", ColumnType::Int),
+            ("source", ColumnType::Text),
+            ("target_id", ColumnType::Int),
+            ("target", ColumnType::Text),
+            ("ability_id", ColumnType::Int),
+            ("ability", ColumnType::Text),
+            ("amount", ColumnType::Int),
+            ("event", ColumnType::Text),
+        ],
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        abilities::{AbilityInfo, AbilityTable},
+        positions::Interpolation,
+        report::test_util::{actor, pull_with},
+    };
+
+    use super::{csv_field, event_table, track_table, Cell};
+
+    #[test]
+    fn track_table_test() {
+        let resources = |x, hit_points| serde_json::json!({"hitPoints": hit_points, "maxHitPoints": 100000, "mp": 10000, "x": x, "y": 0, "facing": 500});
+        let mut pull = pull_with(
+            [(1, actor("Tank, Main", "Player", "Paladin"))],
+            serde_json::json!([
+                {"type": "cast", "abilityGameID": 7, "sourceID": 1, "targetID": 100, "timestamp": 1000, "sourceResources": resources(0, 100000)},
+                {"type": "damage", "abilityGameID": 31000, "amount": 40000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 1500, "targetResources": resources(500, 60000)},
+                {"type": "cast", "abilityGameID": 7, "sourceID": 1, "targetID": 100, "timestamp": 2000, "sourceResources": resources(1000, 60000)}
+            ]),
+        );
+        pull.fight.start_time = 500.0;
+        pull.fight.end_time = 3000.0;
+
+        // Only between the first and last time the log mentions the player
+        let table = track_table(&pull, 4.0, Interpolation::Linear);
+        let rows = table
+            .rows
+            .iter()
+            .map(|row| match (&row[0], &row[4], &row[7]) {
+                (Cell::Float(time), Cell::Float(x), Cell::Int(hp)) => (*time, *x, *hp),
+                _ => panic!("unexpected row {:?}", row),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                (500.0, 0.0, 100000),
+                (750.0, 250.0, 100000),
+                (1000.0, 500.0, 60000),
+                (1250.0, 750.0, 60000),
+                (1500.0, 1000.0, 60000),
+            ]
+        );
+
+        let step_xs = track_table(&pull, 4.0, Interpolation::Step)
+            .rows
+            .iter()
+            .map(|row| row[4].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            step_xs,
+            [0.0, 0.0, 500.0, 500.0, 1000.0].map(Cell::Float).to_vec()
+        );
+
+        let csv = table.to_csv();
+        assert_eq!(
+            csv.lines().take(2).collect::<Vec<_>>(),
+            vec![
+                "timestamp,actor_id,name,job,x,y,facing,hp",
+                "500,1,\"Tank, Main\",Paladin,0,0,1.5707963267948966,100000",
+            ]
+        );
+        assert_eq!(
+            table.to_json_lines().lines().next(),
+            Some(
+                r#"{"timestamp":500.0,"actor_id":1,"name":"Tank, Main","job":"Paladin","x":0.0,"y":0.0,"facing":1.5707963267948966,"hp":100000}"#
+            )
+        );
+    }
+
+    #[test]
+    fn event_table_test() {
+        let mut abilities = AbilityTable::default();
+        abilities.insert(
+            31000,
+            AbilityInfo {
+                name: "Flail, \"Gaoler's\"".to_string(),
+                icon: String::new(),
+                type_: "1024".to_string(),
+            },
+        );
+        let mut pull = pull_with(
+            [
+                (1, actor("Player", "Player", "Paladin")),
+                (100, actor("Boss", "NPC", "Boss")),
+            ],
+            serde_json::json!([
+                {"type": "damage", "abilityGameID": 31000, "amount": 5000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 1500},
+                {"type": "cast", "abilityGameID": 7, "sourceID": 1, "targetID": 200, "timestamp": 2000}
+            ]),
+        );
+        pull.abilities = abilities;
+        pull.fight.start_time = 500.0;
+
+        let table = event_table(&pull);
+        assert_eq!(
+            table
+                .columns
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+            vec![
+                "timestamp",
+                "type",
+                "source_id",
+                "source",
+                "target_id",
+                "target",
+                "ability_id",
+                "ability",
+                "amount",
+                "event"
+            ]
+        );
+        // Actors and abilities the report doesn't know stay empty or fall back to the ID
+        assert_eq!(
+            table.rows[1][..9],
+            [
+                Cell::Float(1500.0),
+                Cell::Text("cast".to_string()),
+                Cell::Int(1),
+                Cell::Text("Player".to_string()),
+                Cell::Int(200),
+                Cell::Null,
+                Cell::Int(7),
+                Cell::Text("#7".to_string()),
+                Cell::Null,
+            ]
+        );
+
+        let csv = table.to_csv();
+        let damage = csv.lines().nth(1).unwrap();
+        assert!(damage.starts_with(
+            "1000,damage,100,Boss,1,Player,31000,\"Flail, \"\"Gaoler's\"\"\",5000,\"{"
+        ));
+        let Cell::Text(event) = &table.rows[0][9] else {
+            panic!("unexpected event cell {:?}", table.rows[0][9]);
+        };
+        assert!(damage.ends_with(&csv_field(event)));
+        assert!(csv
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("1500,cast,1,Player,200,,7,#7,,"));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use crate::{events::Event, report::Pull};
+
+// How long after its calculated damage a damage event can still land for the same hit
+const DAMAGE_DELAY: i64 = 3000;
+// A death this soon after a hit counts as caused by it
+const KILLING_BLOW_WINDOW: i64 = 2000;
+
+/// Damage a player took from an enemy (or from the environment).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamageHit {
+    pub source: i64,
+    pub target: i64,
+    pub ability_id: i64,
+    pub name: String,
+    pub amount: i64,
+    pub overkill: i64,
+    pub timestamp: f64,
+    // The target died from this hit
+    pub fatal: bool,
+    // The target's hit points and max hit points as the event reported them, if it did
+    pub hp: Option<(i64, i64)>,
+}
+
+/// Every hit players took from enemies in the pull, in order. Hits normally show up as a damage
+/// event; calculated damage is only used for the hits that never got one.
+pub fn damage_taken(pull: &Pull) -> Vec<DamageHit> {
+    let actor_type = |id: i64| pull.actors.get(&id).map(|actor| actor.type_.as_str());
+    // Sources the logs don't know about (-1) are the arena itself
+    let is_enemy = |id: i64| matches!(actor_type(id), None | Some("NPC"));
+    let is_player = |id: i64| actor_type(id) == Some("Player");
+
+    let mut landed: HashMap<(i64, i64, i64), Vec<i64>> = HashMap::new();
+    for event in &pull.events {
+        if let Event::Damage {
+            ability_game_id,
+            source,
+            target,
+            timestamp,
+            ..
+        } = event
+        {
+            landed
+                .entry((source.id, target.id, *ability_game_id))
+                .or_default()
+                .push(*timestamp);
+        }
+    }
+
+    let mut hits = Vec::new();
+    for event in &pull.events {
+        let (ability_id, amount, overkill, source, target, timestamp) = match event {
+            Event::Damage {
+                ability_game_id,
+                amount,
+                overkill,
+                source,
+                target,
+                timestamp,
+                ..
+            } => (
+                *ability_game_id,
+                *amount,
+                overkill.unwrap_or(0),
+                source.id,
+                target.id,
+                *timestamp,
+            ),
+            Event::CalculatedDamage {
+                ability_game_id,
+                amount,
+                source,
+                target,
+                timestamp,
+                ..
+            } => {
+                let has_damage = landed
+                    .get(&(source.id, target.id, *ability_game_id))
+                    .is_some_and(|times| {
+                        times
+                            .iter()
+                            .any(|t| (*timestamp..=timestamp + DAMAGE_DELAY).contains(t))
+                    });
+                if has_damage {
+                    continue;
+                }
+                (
+                    *ability_game_id,
+                    *amount,
+                    0,
+                    source.id,
+                    target.id,
+                    *timestamp,
+                )
+            }
+            _ => continue,
+        };
+        if !is_enemy(source) || !is_player(target) {
+            continue;
+        }
+
+        hits.push(DamageHit {
+            source,
+            target,
+            ability_id,
+            name: pull.abilities.name(ability_id),
+            amount,
+            overkill,
+            timestamp: timestamp as f64,
+            fatal: overkill > 0,
+            hp: event
+                .get_target_resources()
+                .map(|(_, resources)| (resources.hit_points, resources.max_hit_points)),
+        });
+    }
+
+    // Whatever hit a player last right before they died killed them, even without overkill
+    for event in &pull.events {
+        if let Event::Death {
+            target, timestamp, ..
+        } = event
+        {
+            if let Some(index) = killing_blow_index(&hits, target.id, *timestamp as f64) {
+                hits[index].fatal = true;
+            }
+        }
+    }
+
+    hits
+}
+
+fn killing_blow_index(hits: &[DamageHit], target: i64, death_time: f64) -> Option<usize> {
+    hits.iter().rposition(|hit| {
+        hit.target == target
+            && hit.timestamp <= death_time
+            && death_time - hit.timestamp <= KILLING_BLOW_WINDOW as f64
+    })
+}
+
+/// The hit that killed `target` at `death_time`, out of the hits from `damage_taken`.
+pub fn killing_blow(hits: &[DamageHit], target: i64, death_time: f64) -> Option<&DamageHit> {
+    killing_blow_index(hits, target, death_time).map(|index| &hits[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::test_util::{actor, pull_with};
+
+    use super::damage_taken;
+
+    #[test]
+    fn damage_taken_test() {
+        let pull = pull_with(
+            [
+                (1, actor("Player 1", "Player", "Paladin")),
+                (2, actor("Player 2", "Player", "BlackMage")),
+                (100, actor("Hephaistos", "NPC", "Boss")),
+            ],
+            serde_json::json!([
+                // Player 1's hit lands 600ms after it's calculated, so only the damage event counts;
+                // player 2's never lands
+                {"type": "calculateddamage", "abilityGameID": 31000, "amount": 20000, "multiplier": 1.0, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 1000},
+                {"type": "calculateddamage", "abilityGameID": 31000, "amount": 20000, "multiplier": 1.0, "hitType": 1, "sourceID": 100, "targetID": 2, "timestamp": 1000},
+                {"type": "damage", "abilityGameID": 31000, "amount": 19000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 1600},
+                // Players hitting the boss don't count
+                {"type": "damage", "abilityGameID": 7, "amount": 1000, "hitType": 1, "sourceID": 1, "targetID": 100, "timestamp": 2000},
+                {"type": "damage", "abilityGameID": 31001, "amount": 150000, "overkill": 50000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 5000},
+                {"type": "damage", "abilityGameID": 31002, "amount": 30000, "hitType": 1, "sourceID": -1, "targetID": 2, "timestamp": 6000},
+                {"type": "death", "sourceID": -1, "targetID": 2, "timestamp": 6500}
+            ]),
+        );
+
+        let hits = damage_taken(&pull);
+        let summary = hits
+            .iter()
+            .map(|hit| {
+                (
+                    hit.target,
+                    hit.name.as_str(),
+                    hit.amount,
+                    hit.overkill,
+                    hit.timestamp,
+                    hit.fatal,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (2, "#31000", 20000, 0, 1000.0, false),
+                (1, "#31000", 19000, 0, 1600.0, false),
+                (1, "#31001", 150000, 50000, 5000.0, true),
+                (2, "#31002", 30000, 0, 6000.0, true),
+            ]
+        );
+    }
+}
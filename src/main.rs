@@ -1,279 +1,1180 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     error::Error,
     io::Write,
-    time::Duration,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use avoidable::AvoidableConfig;
+use character::CharacterRef;
+use clap::{Args, Parser, Subcommand};
 use client::Client;
-use events::Event;
-use humantime::format_duration;
-use positions::PositionHistory;
-use queries::report_fights::ReportFightsReportDataReport;
-use serde_json::Value;
+use discovery::ReportOwner;
+use encounters::EncounterSpec;
+use positions::Interpolation;
+use report::{Anchor, FightSummary, Pull, PullSpec, Report};
+use serde::{Deserialize, Serialize};
 
-use crate::video::render_animations;
+use crate::video::{
+    render_animations, render_grid, render_overlay, render_snapshots, OverlaySource, PULL_TINTS,
+};
 
+mod abilities;
+mod act_log;
+mod avoidable;
+mod casts;
+mod character;
 mod client;
+mod deaths;
+mod discovery;
+mod encode;
+mod encounters;
 mod events;
+mod export;
+mod hits;
 mod positions;
+mod pull_file;
 mod queries;
+mod rate_limit;
+mod report;
+mod server;
+mod tui;
 mod video;
+mod viewer;
 
-// const P5S_ENCOUNTER_ID: i64 = 83;
-// const P6S_ENCOUNTER_ID: i64 = 84;
-// const P7S_ENCOUNTER_ID: i64 = 85;
-// const P8S_P1_ENCOUNTER_ID: i64 = 86;
-// const P8S_P2_ENCOUNTER_ID: i64 = 87;
-
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActorInfo {
     pub name: String,
+    #[serde(rename = "type")]
     type_: String,
     subtype: String,
 }
 
-fn get_report_codes(character_rankings: &Value) -> Option<Vec<String>> {
-    let mut result = Vec::new();
+#[derive(Parser)]
+#[command(about = "Renders FF Logs fights as mechanic replay videos")]
+struct Cli {
+    /// Keep API responses in this directory and reuse them instead of querying again
+    #[arg(long, global = true, env = "FFLOGS_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
 
-    for report in character_rankings.as_object()?.get("ranks")?.as_array()? {
-        let report = report.as_object()?.get("report")?.as_object()?;
-        let code = report.get("code")?.as_str()?;
+    /// Never contact FF Logs; everything has to come from the cache directory
+    #[arg(long, global = true, requires = "cache_dir")]
+    offline: bool,
 
-        result.push(code.to_string());
-    }
+    /// Hold off on queries that would leave fewer than this many points of the hourly API budget
+    #[arg(long, global = true, default_value_t = rate_limit::DEFAULT_THRESHOLD)]
+    rate_limit_threshold: f64,
+
+    /// Fail instead of waiting for the hourly budget to reset when it runs low
+    #[arg(long, global = true)]
+    fail_on_rate_limit: bool,
 
-    Some(result)
+    #[command(subcommand)]
+    command: Command,
 }
 
-async fn load_all_events(
-    client: &Client,
-    code: &str,
-    start_time: f64,
-    end_time: f64,
-    fight_id: i64,
-) -> Result<Vec<Event>, Box<dyn Error>> {
-    let mut result = Vec::new();
-
-    let mut page_start = start_time;
-    while page_start < end_time {
-        let events = client
-            .query::<queries::ReportEvents>(queries::report_events::Variables {
-                code: code.to_string(),
-                start_time: page_start,
-                end_time: end_time,
-                fight_ids: vec![fight_id],
-            })
-            .await?;
-
-        let mut report = events.report_data.unwrap().report.unwrap().events.unwrap();
-        let events = report.data.as_mut().unwrap();
-        result.append(events);
-
-        page_start = if let Some(page_start) = report.next_page_timestamp {
-            page_start
-        } else {
-            break;
-        };
+#[derive(Args)]
+struct VideoArgs {
+    /// Output file; the format follows the extension: .mp4 (needs ffmpeg), .gif, .png/.apng or
+    /// .webp. Defaults to --name-template inside --output-dir
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Directory for outputs that aren't given an explicit path
+    #[arg(long, default_value = "output")]
+    output_dir: PathBuf,
+
+    /// File name template; {code}, {fight}, {boss} and {kill} are filled in from the pulls
+    #[arg(long, default_value = report::DEFAULT_OUTPUT_NAME)]
+    name_template: String,
+
+    /// Video resolution as WIDTHxHEIGHT (or a single number for a square); the arena is
+    /// letterboxed into non-square sizes
+    #[arg(long, value_parser = parse_frame_size)]
+    size: Option<(u32, u32)>,
+
+    /// How to fill in positions between samples: step, linear, catmull-rom or max-speed
+    #[arg(long, default_value = "linear", value_parser = positions::parse_interpolation)]
+    interpolation: Interpolation,
+}
+impl VideoArgs {
+    fn output_path(&self, pulls: &[Pull]) -> PathBuf {
+        match &self.output {
+            Some(output) => output.clone(),
+            None => self
+                .output_dir
+                .join(report::output_file_name(&self.name_template, pulls)),
+        }
     }
+}
+
+const DEFAULT_FRAME_SIZE: (u32, u32) = (1024, 1024);
 
-    Ok(result)
+#[derive(Args)]
+struct DamageReportArgs {
+    /// Also list each player's avoidable damage and deaths; printed as a table, or written to the
+    /// given .txt, .json or .csv file
+    #[arg(long, num_args = 0..=1, value_name = "OUTPUT")]
+    damage_report: Option<Option<PathBuf>>,
+
+    /// JSON file listing the avoidable ability IDs of each encounter, as
+    /// {"ENCOUNTER_ID": {"avoidable": [ABILITY_ID, ...]}}
+    #[arg(long, default_value = "avoidable.json")]
+    avoidable_config: PathBuf,
+
+    /// Only write the damage report, without rendering the fight
+    #[arg(long, requires = "damage_report")]
+    no_video: bool,
 }
 
-async fn handle_fight(
-    client: &Client,
-    code: &str,
-    report_data: &ReportFightsReportDataReport,
-    idx: usize,
-    actors: &HashMap<i64, ActorInfo>,
-) -> Result<(), Box<dyn Error>> {
-    let fight_data = report_data.fights.as_ref().unwrap()[idx].as_ref().unwrap();
-    let fight_start_time = fight_data.start_time;
-    let fight_end_time = fight_data.end_time;
-    let fight_length = Duration::from_millis((fight_end_time - fight_start_time) as u64);
-    let bounding_box = fight_data.bounding_box.as_ref().unwrap();
-    let bounding_box = (
-        (bounding_box.min_x as f64, bounding_box.min_y as f64),
-        (bounding_box.max_x as f64, bounding_box.max_y as f64),
-    );
+#[derive(Subcommand)]
+enum Command {
+    /// Render a single fight from a report, prompting for the fight if it isn't given
+    Render {
+        /// Report code, a pull file saved by fetch, or an ACT network log
+        code: String,
+        #[arg(long)]
+        fight: Option<i64>,
 
-    println!("Loading report...");
+        #[command(flatten)]
+        damage: DamageReportArgs,
 
-    let events = load_all_events(
-        client,
-        code,
-        fight_data.start_time,
-        fight_data.end_time,
-        fight_data.id,
-    )
-    .await?;
+        #[command(flatten)]
+        video: VideoArgs,
+    },
 
-    println!(
-        "Loaded {} events! ({} events/second of fight)",
-        events.len(),
-        events.len() as f64 / fight_length.as_secs_f64()
-    );
+    /// Save a pull's fight details, actors, ability names and raw events to one file, which every
+    /// other command takes in place of CODE:FIGHT_ID without needing FF Logs
+    Fetch {
+        /// Pull to save, as CODE:FIGHT_ID
+        #[arg(value_parser = parse_pull_spec)]
+        pull: PullSpec,
 
-    // id -> position history
-    let mut position_history: HashMap<i64, PositionHistory> = HashMap::with_capacity(actors.len());
+        /// Output file; defaults to output/CODE_FIGHT.json
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 
-    for event in events {
-        let time = event.get_timestamp();
+    /// Render several pulls on top of each other, each in a different tint
+    Overlay {
+        /// Pulls to compare, as CODE:FIGHT_ID or local files (saved by fetch, or ACT .log files, as FILE:FIGHT_ID when a log holds several pulls)
+        #[arg(required = true, value_parser = parse_pull_spec)]
+        pulls: Vec<PullSpec>,
 
-        if let Some((id, res)) = event.get_source_resources() {
-            position_history
-                .entry(id)
-                .or_default()
-                .add_update(time, (res.x as f64, res.y as f64));
-        }
+        /// Line pulls up on the Nth (default 1st) cast of an ability instead of on pull start,
+        /// as ABILITY_ID or ABILITY_ID:N
+        #[arg(long, value_parser = parse_anchor)]
+        anchor: Option<Anchor>,
+
+        #[command(flatten)]
+        video: VideoArgs,
+    },
+
+    /// Render several pulls side by side in a grid, synchronized on pull start or an anchor cast
+    Grid {
+        /// Pulls to compare, as CODE:FIGHT_ID or local files (saved by fetch, or ACT .log files, as FILE:FIGHT_ID when a log holds several pulls)
+        #[arg(required = true, value_parser = parse_pull_spec)]
+        pulls: Vec<PullSpec>,
+
+        /// Line pulls up on the Nth (default 1st) cast of an ability instead of on pull start,
+        /// as ABILITY_ID or ABILITY_ID:N
+        #[arg(long, value_parser = parse_anchor)]
+        anchor: Option<Anchor>,
+
+        /// Number of cells per row; defaults to the smallest square grid that fits every pull
+        #[arg(long)]
+        columns: Option<usize>,
+
+        /// Width and height of each cell in pixels; with --size the cells are fitted to it instead
+        #[arg(long, default_value_t = 512)]
+        cell_size: u32,
+
+        #[command(flatten)]
+        video: VideoArgs,
+    },
+
+    /// List (and optionally render) every boss pull from a guild's or user's reports in a date range
+    Discover {
+        /// Guild name, looked up together with --server and --region
+        #[arg(long, requires_all = ["server", "region"], required_unless_present_any = ["guild_id", "user_id"])]
+        guild: Option<String>,
+
+        /// Server slug of the guild, e.g. gilgamesh
+        #[arg(long)]
+        server: Option<String>,
+
+        /// Server region of the guild, e.g. NA, EU or JP
+        #[arg(long)]
+        region: Option<String>,
+
+        /// FF Logs guild ID
+        #[arg(long, conflicts_with_all = ["guild", "user_id"])]
+        guild_id: Option<i64>,
+
+        /// FF Logs user ID
+        #[arg(long, conflicts_with_all = ["guild", "guild_id"])]
+        user_id: Option<i64>,
+
+        /// Start of the range, as YYYY-MM-DD[ HH:MM] or a span back from now like 7days
+        #[arg(long, value_parser = discovery::parse_date)]
+        since: SystemTime,
+
+        /// End of the range, in the same formats; defaults to now
+        #[arg(long, value_parser = discovery::parse_date)]
+        until: Option<SystemTime>,
+
+        /// Only reports from this zone
+        #[arg(long)]
+        zone: Option<i64>,
+
+        /// Only pulls of this encounter, as an ID, a short name like p8s2 or TOP, or (part of) its
+        /// full name
+        #[arg(long, value_parser = encounters::parse_encounter_spec)]
+        encounter: Option<EncounterSpec>,
+
+        /// Skip wipes
+        #[arg(long)]
+        kills_only: bool,
+
+        /// Render every pull found instead of just listing them
+        #[arg(long)]
+        render: bool,
+
+        #[command(flatten)]
+        video: VideoArgs,
+    },
+
+    /// Render the fights behind a character's encounter rankings, best parse first
+    Character {
+        /// FF Logs character ID
+        #[arg(long, required_unless_present = "name", conflicts_with = "name")]
+        id: Option<i64>,
+
+        /// Character name, looked up together with --server and --region
+        #[arg(long, requires_all = ["server", "region"])]
+        name: Option<String>,
+
+        /// Server slug, e.g. gilgamesh
+        #[arg(long)]
+        server: Option<String>,
+
+        /// Server region, e.g. NA, EU or JP
+        #[arg(long)]
+        region: Option<String>,
+
+        /// Encounter to take the rankings from, as an ID, a short name like p8s2 or TOP, or (part of)
+        /// its full name
+        #[arg(long, value_parser = encounters::parse_encounter_spec)]
+        encounter: EncounterSpec,
+
+        /// How many of the best ranked fights to render
+        #[arg(long, default_value_t = 5)]
+        limit: usize,
+
+        /// Render every fight into one overlay video instead of one video each
+        #[arg(long)]
+        overlay: bool,
+
+        /// With --overlay, line pulls up on the Nth (default 1st) cast of an ability, as
+        /// ABILITY_ID or ABILITY_ID:N
+        #[arg(long, value_parser = parse_anchor, requires = "overlay")]
+        anchor: Option<Anchor>,
+
+        #[command(flatten)]
+        video: VideoArgs,
+    },
+
+    /// Render the fastest kills of an encounter from its speed rankings
+    Rankings {
+        /// Encounter to take the rankings from, as an ID, a short name like p8s2 or TOP, or (part of)
+        /// its full name
+        #[arg(long, value_parser = encounters::parse_encounter_spec)]
+        encounter: EncounterSpec,
+
+        /// How many of the fastest kills to render
+        #[arg(long, default_value_t = 5)]
+        limit: usize,
+
+        /// Render every kill into one overlay video instead of one video each
+        #[arg(long)]
+        overlay: bool,
+
+        /// With --overlay, line pulls up on the Nth (default 1st) cast of an ability, as
+        /// ABILITY_ID or ABILITY_ID:N
+        #[arg(long, value_parser = parse_anchor, requires = "overlay")]
+        anchor: Option<Anchor>,
+
+        #[command(flatten)]
+        video: VideoArgs,
+    },
+
+    /// List zones and their encounters, to find IDs for --zone and --encounter
+    Zones {
+        /// Only list the zones of this expansion ID
+        #[arg(long)]
+        expansion: Option<i64>,
+    },
+
+    /// List the guilds on a server, to find IDs for --guild-id
+    Guilds {
+        /// Server slug, e.g. gilgamesh
+        #[arg(long)]
+        server: String,
+
+        /// Server region, e.g. NA, EU or JP
+        #[arg(long)]
+        region: String,
+
+        /// Page of the list to show
+        #[arg(long, default_value_t = 1)]
+        page: i64,
+    },
+
+    /// Export a pull as a self-contained interactive HTML viewer
+    Viewer {
+        /// Pull to export, as CODE:FIGHT_ID or a local file (saved by fetch, or an ACT .log file, as FILE:FIGHT_ID when it holds several pulls)
+        #[arg(value_parser = parse_pull_spec)]
+        pull: PullSpec,
+
+        /// Output file; defaults to output/CODE_FIGHT.html
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Render still images of a pull at chosen moments
+    Snapshot {
+        /// Pull to draw, as CODE:FIGHT_ID or a local file (saved by fetch, or an ACT .log file, as FILE:FIGHT_ID when it holds several pulls)
+        #[arg(value_parser = parse_pull_spec)]
+        pull: PullSpec,
 
-        if let Some((id, res)) = event.get_target_resources() {
-            position_history
-                .entry(id)
-                .or_default()
-                .add_update(time, (res.x as f64, res.y as f64));
+        /// Time since the pull started, as M:SS.S or seconds; can be given several times
+        #[arg(long = "at", value_parser = report::parse_fight_time)]
+        at: Vec<f64>,
+
+        /// Also take a snapshot at every cast of this ability
+        #[arg(long)]
+        every_cast: Option<i64>,
+
+        /// Draw each player's path over this many preceding seconds
+        #[arg(long, default_value_t = 0.0)]
+        trail: f64,
+
+        /// Width and height of the image
+        #[arg(long, default_value_t = 1024)]
+        size: u32,
+
+        /// Output file: .svg, .pdf (one page per moment) or .png
+        #[arg(short, long, default_value = "output/snapshot.svg")]
+        output: PathBuf,
+
+        /// How to fill in positions between samples: step, linear, catmull-rom or max-speed
+        #[arg(long, default_value = "linear", value_parser = positions::parse_interpolation)]
+        interpolation: Interpolation,
+    },
+
+    /// Write a markdown recap of every death in a pull, with a snapshot of each
+    Deaths {
+        /// Pull to recap, as CODE:FIGHT_ID or a local file (saved by fetch, or an ACT .log file, as FILE:FIGHT_ID when it holds several pulls)
+        #[arg(value_parser = parse_pull_spec)]
+        pull: PullSpec,
+
+        /// Seconds of lead-up to cover before each death
+        #[arg(long, default_value_t = deaths::DEFAULT_RECAP_WINDOW)]
+        window: f64,
+
+        /// Width and height of the snapshots
+        #[arg(long, default_value_t = 512)]
+        size: u32,
+
+        /// Markdown file to write; the snapshots are saved next to it
+        #[arg(short, long, default_value = "output/deaths.md")]
+        output: PathBuf,
+
+        /// How to fill in positions between samples: step, linear, catmull-rom or max-speed
+        #[arg(long, default_value = "linear", value_parser = positions::parse_interpolation)]
+        interpolation: Interpolation,
+    },
+
+    /// Export every actor's resampled track and the pull's events as tables for analysis elsewhere
+    Export {
+        /// Pull to export, as CODE:FIGHT_ID or a local file (saved by fetch, or an ACT .log file, as FILE:FIGHT_ID when it holds several pulls)
+        #[arg(value_parser = parse_pull_spec)]
+        pull: PullSpec,
+
+        /// Track samples per second
+        #[arg(long, default_value_t = 4.0)]
+        rate: f64,
+
+        /// Output file for the tracks; the format follows the extension: .csv, .jsonl or .parquet
+        /// (needs the parquet feature)
+        #[arg(long, default_value = "output/tracks.csv")]
+        tracks: PathBuf,
+
+        /// Output file for the events, in the same formats
+        #[arg(long, default_value = "output/events.csv")]
+        events: PathBuf,
+
+        /// How to fill in positions between samples: step, linear, catmull-rom or max-speed
+        #[arg(long, default_value = "linear", value_parser = positions::parse_interpolation)]
+        interpolation: Interpolation,
+    },
+
+    /// Play a pull back in the terminal
+    Play {
+        /// Pull to play, as CODE:FIGHT_ID or a local file (saved by fetch, or an ACT .log file, as FILE:FIGHT_ID when it holds several pulls)
+        #[arg(value_parser = parse_pull_spec)]
+        pull: PullSpec,
+
+        /// How to fill in positions between samples: step, linear, catmull-rom or max-speed
+        #[arg(long, default_value = "linear", value_parser = positions::parse_interpolation)]
+        interpolation: Interpolation,
+    },
+
+    /// Run a local web server for browsing reports and rendering fights on demand
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+    },
+}
+impl Command {
+    // Local pull files (saved pulls and ACT logs) are read without FF Logs, so commands working
+    // only on those don't need an API token
+    fn needs_api(&self) -> bool {
+        let is_file = |spec: &PullSpec| matches!(spec, PullSpec::File { .. });
+        match self {
+            Command::Render { code, .. } => !pull_file::is_pull_file(code),
+            Command::Overlay { pulls, .. } | Command::Grid { pulls, .. } => {
+                !pulls.iter().all(is_file)
+            }
+            Command::Fetch { pull, .. }
+            | Command::Viewer { pull, .. }
+            | Command::Snapshot { pull, .. }
+            | Command::Deaths { pull, .. }
+            | Command::Export { pull, .. }
+            | Command::Play { pull, .. } => !is_file(pull),
+            Command::Discover { .. }
+            | Command::Character { .. }
+            | Command::Rankings { .. }
+            | Command::Zones { .. }
+            | Command::Guilds { .. }
+            | Command::Serve { .. } => true,
         }
     }
+}
 
-    render_animations(
-        &position_history,
-        actors,
-        fight_data.start_time,
-        fight_data.end_time,
-        bounding_box,
-        1024,
-        "output",
-    );
+fn parse_pull_spec(spec: &str) -> Result<PullSpec, String> {
+    if pull_file::is_pull_file(spec) {
+        return Ok(PullSpec::File {
+            path: PathBuf::from(spec),
+            fight_id: None,
+        });
+    }
 
-    Ok(())
+    // Split at the last colon, so paths with drive letters still work
+    let (code, fight) = spec.rsplit_once(':').ok_or_else(|| {
+        format!(
+            "expected CODE:FIGHT_ID or a .json/.log file, got {:?}",
+            spec
+        )
+    })?;
+    let fight_id = fight
+        .parse()
+        .map_err(|e| format!("bad fight ID {:?}: {}", fight, e))?;
+    if pull_file::is_pull_file(code) {
+        return Ok(PullSpec::File {
+            path: PathBuf::from(code),
+            fight_id: Some(fight_id),
+        });
+    }
+    Ok(PullSpec::Report {
+        code: code.to_string(),
+        fight_id,
+    })
 }
 
-async fn read_report(client: &Client, code: &str) -> Result<(), Box<dyn Error>> {
-    let fight_data = client
-        .query::<queries::ReportFights>(queries::report_fights::Variables {
-            code: code.to_string(),
-        })
-        .await?
-        .report_data
-        .unwrap()
-        .report
-        .unwrap();
-
-    let actors = fight_data
-        .master_data
-        .as_ref()
-        .unwrap()
-        .actors
-        .as_ref()
-        .unwrap()
-        .into_iter()
-        .map(|actor| {
-            let actor = actor.as_ref().unwrap();
-            let data = ActorInfo {
-                name: actor.name.as_ref().unwrap().clone(),
-                type_: actor.type_.as_ref().unwrap().clone(),
-                subtype: actor.sub_type.as_ref().unwrap().clone(),
-            };
-            (actor.id.unwrap() as i64, data)
-        })
-        .collect::<HashMap<_, _>>();
+fn parse_frame_size(spec: &str) -> Result<(u32, u32), String> {
+    let (width, height) = spec.split_once(['x', 'X']).unwrap_or((spec, spec));
+    let parse = |text: &str| match text.trim().parse::<u32>() {
+        Ok(0) => Err("frame size can't be 0".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(format!("bad frame size {:?}: {}", spec, e)),
+    };
+    Ok((parse(width)?, parse(height)?))
+}
 
-    let fights = fight_data.fights.as_ref().unwrap();
-    for (i, fight) in fights.iter().enumerate() {
-        let fight = fight.as_ref().unwrap();
-
-        let mut enemies = fight
-            .enemy_np_cs
-            .as_ref()
-            .unwrap()
-            .iter()
-            .filter_map(|npc| {
-                let id = npc.as_ref().unwrap().id.unwrap();
-                let name = &actors.get(&id).as_ref().unwrap().name;
-                if name == "Multiple Enemies" {
-                    None
-                } else {
-                    Some(name.to_string())
-                }
-            })
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect::<Vec<_>>();
-        enemies.sort();
-
-        let kill = fight.kill.unwrap();
-        let fight_duration = Duration::from_millis((fight.end_time - fight.start_time) as u64);
-        let kill_text = if kill {
-            format!("killed in {}", format_duration(fight_duration))
-        } else {
-            format!(
-                "wiped at {}% after {}",
-                fight.fight_percentage.unwrap(),
-                format_duration(fight_duration)
-            )
-        };
+fn parse_anchor(spec: &str) -> Result<Anchor, String> {
+    let (ability, occurrence) = spec.split_once(':').unwrap_or((spec, "1"));
+    let ability_id = ability
+        .parse()
+        .map_err(|e| format!("bad ability ID {:?}: {}", ability, e))?;
+    let occurrence = occurrence
+        .parse()
+        .map_err(|e| format!("bad occurrence {:?}: {}", occurrence, e))?;
+    if occurrence == 0 {
+        return Err("occurrences are counted from 1".to_string());
+    }
+    Ok(Anchor::Cast {
+        ability_id,
+        occurrence,
+    })
+}
+
+fn render_pull(pull: &Pull, video: &VideoArgs) -> Result<(), Box<dyn Error>> {
+    render_animations(
+        pull,
+        video.interpolation,
+        video.size.unwrap_or(DEFAULT_FRAME_SIZE),
+        video.output_path(std::slice::from_ref(pull)),
+    )
+}
+
+fn handle_fight(
+    pull: &Pull,
+    damage: &DamageReportArgs,
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(output) = &damage.damage_report {
+        let config = AvoidableConfig::load(&damage.avoidable_config)?;
+        avoidable::damage_report(pull, &config).write(output.as_deref())?;
+    }
+    if damage.no_video {
+        return Ok(());
+    }
 
+    render_pull(pull, video)
+}
+
+fn select_fight(fights: &[FightSummary]) -> i64 {
+    for (i, fight) in fights.iter().enumerate() {
         println!(
-            "{i}: Fight {} against {enemies:?} ({})",
-            fight.id, kill_text
+            "{i}: Fight {} against {:?} ({})",
+            fight.id,
+            fight.enemies,
+            fight.outcome_text()
         );
     }
 
     let mut buf = String::new();
-    let sel: usize = loop {
+    loop {
         print!("Select a fight: ");
         std::io::stdout().flush().unwrap();
+        buf.clear();
         if let Err(e) = std::io::stdin().read_line(&mut buf) {
             println!("{:?}", e);
             continue;
         }
-        if let Ok(sel) = buf.trim().parse() {
-            if sel < fights.len() {
-                break sel;
+        if let Ok(sel) = buf.trim().parse::<usize>() {
+            if let Some(fight) = fights.get(sel) {
+                return fight.id;
+            }
+        }
+    }
+}
+
+async fn read_report(
+    client: &Client,
+    code: &str,
+    fight: Option<i64>,
+    damage: &DamageReportArgs,
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    let pull = if pull_file::is_pull_file(code) {
+        let path = Path::new(code);
+        let pulls = pull_file::load_all(path)?;
+        let fight_id = match fight {
+            None if pulls.len() > 1 => Some(select_fight(
+                &pulls
+                    .iter()
+                    .map(|pull| pull.fight.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            fight => fight,
+        };
+        pull_file::select(path, pulls, fight_id)?
+    } else {
+        let report = report::load_report(client, code).await?;
+
+        let fight_id = match fight {
+            Some(fight_id) => fight_id,
+            None => select_fight(&report.fights),
+        };
+        report::load_pull(client, &report, fight_id).await?
+    };
+
+    handle_fight(&pull, damage, video)
+}
+
+// Loads one pull, reusing (and adding to) the reports loaded for earlier pulls.
+async fn load_pull_spec(
+    client: &Client,
+    reports: &mut HashMap<String, Report>,
+    spec: &PullSpec,
+) -> Result<Pull, Box<dyn Error>> {
+    match spec {
+        PullSpec::Report { code, fight_id } => {
+            if !reports.contains_key(code) {
+                reports.insert(code.clone(), report::load_report(client, code).await?);
             }
+            report::load_pull(client, &reports[code], *fight_id).await
         }
+        PullSpec::File { path, fight_id } => pull_file::load(path, *fight_id),
+    }
+}
+
+async fn load_pulls(client: &Client, specs: &[PullSpec]) -> Result<Vec<Pull>, Box<dyn Error>> {
+    let mut reports: HashMap<String, Report> = HashMap::new();
+    let mut pulls: Vec<Pull> = Vec::with_capacity(specs.len());
+
+    for spec in specs {
+        pulls.push(load_pull_spec(client, &mut reports, spec).await?);
+    }
+
+    Ok(pulls)
+}
+
+fn aligned_sources(
+    pulls: &[Pull],
+    anchor: Anchor,
+    tinted: bool,
+    interpolation: Interpolation,
+) -> Result<Vec<OverlaySource<'_>>, Box<dyn Error>> {
+    let mut sources = Vec::with_capacity(pulls.len());
+    for (i, pull) in pulls.iter().enumerate() {
+        let time_offset = pull
+            .anchor_time(anchor)
+            .ok_or_else(|| format!("{} has no event matching {:?}", pull.label(), anchor))?;
+
+        sources.push(OverlaySource {
+            history: &pull.positions,
+            actors: &pull.actors,
+            time_offset,
+            start_time: pull.fight.start_time,
+            end_time: pull.fight.end_time,
+            bounding_box: pull.fight.bounding_box,
+            tint: tinted.then_some(PULL_TINTS[i % PULL_TINTS.len()]),
+            label: format!("{} ({})", pull.label(), pull.fight.outcome_text()),
+            casts: casts::boss_casts(pull),
+            hits: hits::damage_taken(pull),
+            interpolation,
+        });
+    }
+
+    Ok(sources)
+}
+
+async fn overlay_pulls(
+    client: &Client,
+    specs: &[PullSpec],
+    anchor: Anchor,
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    let pulls = load_pulls(client, specs).await?;
+    let sources = aligned_sources(&pulls, anchor, true, video.interpolation)?;
+
+    render_overlay(
+        &sources,
+        video.size.unwrap_or(DEFAULT_FRAME_SIZE),
+        video.output_path(&pulls),
+    )
+}
+
+async fn grid_pulls(
+    client: &Client,
+    specs: &[PullSpec],
+    anchor: Anchor,
+    columns: Option<usize>,
+    cell_size: u32,
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    let pulls = load_pulls(client, specs).await?;
+    let sources = aligned_sources(&pulls, anchor, false, video.interpolation)?;
+
+    let columns = columns.unwrap_or_else(|| (sources.len() as f64).sqrt().ceil() as usize);
+    render_grid(
+        &sources,
+        columns,
+        cell_size,
+        video.size,
+        video.output_path(&pulls),
+    )
+}
+
+async fn resolve_encounter(
+    client: &Client,
+    encounter: &EncounterSpec,
+) -> Result<i64, Box<dyn Error>> {
+    // The catalog lives with the API cache if there is one.
+    let catalog_path = client
+        .cache_dir()
+        .unwrap_or_else(|| Path::new("output"))
+        .join("encounters.json");
+    encounters::resolve_encounter(client, &catalog_path, encounter).await
+}
+
+async fn render_character(
+    client: &Client,
+    character: &CharacterRef,
+    encounter: &EncounterSpec,
+    limit: usize,
+    overlay: Option<Anchor>,
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    let encounter_id = resolve_encounter(client, encounter).await?;
+    let (name, mut ranked_fights) =
+        character::load_ranked_fights(client, character, encounter_id).await?;
+    ranked_fights.truncate(limit);
+    if ranked_fights.is_empty() {
+        return Err(format!(
+            "{} has no ranked fights in encounter {}",
+            name, encounter_id
+        )
+        .into());
+    }
+
+    for fight in &ranked_fights {
+        println!(
+            "{}: {} #{} ({:.1} percentile)",
+            name, fight.code, fight.fight_id, fight.rank_percent
+        );
+    }
+    let specs = ranked_fights
+        .into_iter()
+        .map(|fight| PullSpec::Report {
+            code: fight.code,
+            fight_id: fight.fight_id,
+        })
+        .collect::<Vec<_>>();
+
+    match overlay {
+        Some(anchor) => overlay_pulls(client, &specs, anchor, video).await,
+        None => render_batch(client, &specs, video).await,
+    }
+}
+
+async fn render_rankings(
+    client: &Client,
+    encounter: &EncounterSpec,
+    limit: usize,
+    overlay: Option<Anchor>,
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    let encounter_id = resolve_encounter(client, encounter).await?;
+    let (name, kills) = encounters::load_ranked_kills(client, encounter_id, limit).await?;
+    if kills.is_empty() {
+        return Err(format!("{} has no ranked kills", name).into());
+    }
+
+    for kill in &kills {
+        println!(
+            "{}: {} #{} in {} ({})",
+            name,
+            kill.code,
+            kill.fight_id,
+            report::format_fight_time(kill.duration),
+            kill.name
+        );
+    }
+    let specs = kills
+        .into_iter()
+        .map(|kill| PullSpec::Report {
+            code: kill.code,
+            fight_id: kill.fight_id,
+        })
+        .collect::<Vec<_>>();
+
+    match overlay {
+        Some(anchor) => overlay_pulls(client, &specs, anchor, video).await,
+        None => render_batch(client, &specs, video).await,
+    }
+}
+
+// Renders each pull to its own file, loading them one at a time so long batches don't have to fit
+// in memory all at once.
+async fn render_batch(
+    client: &Client,
+    specs: &[PullSpec],
+    video: &VideoArgs,
+) -> Result<(), Box<dyn Error>> {
+    if video.output.is_some() && specs.len() > 1 {
+        return Err("-o would be overwritten by every fight; use --name-template instead".into());
+    }
+
+    let mut reports: HashMap<String, Report> = HashMap::new();
+    for (i, spec) in specs.iter().enumerate() {
+        println!("Rendering pull {} of {}", i + 1, specs.len());
+        let pull = load_pull_spec(client, &mut reports, spec).await?;
+        render_pull(&pull, video)?;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn discover_fights(
+    client: &Client,
+    owner: &ReportOwner,
+    since: SystemTime,
+    until: SystemTime,
+    zone: Option<i64>,
+    encounter: Option<&EncounterSpec>,
+    kills_only: bool,
+    render: Option<&VideoArgs>,
+) -> Result<(), Box<dyn Error>> {
+    let encounter_id = match encounter {
+        Some(encounter) => Some(resolve_encounter(client, encounter).await?),
+        None => None,
     };
 
-    handle_fight(client, code, &fight_data, sel, &actors).await
+    let mut fights =
+        discovery::find_fights(client, owner, since, until, zone, encounter_id).await?;
+    if kills_only {
+        fights.retain(|fight| fight.kill);
+    }
+
+    for fight in &fights {
+        println!(
+            "{}:{}  {} ({})  [{}]",
+            fight.code,
+            fight.fight_id,
+            fight.name,
+            if fight.kill { "kill" } else { "wipe" },
+            fight.report_title
+        );
+    }
+    println!("Found {} pulls", fights.len());
+
+    match render {
+        Some(video) => {
+            let specs = fights
+                .into_iter()
+                .map(|fight| PullSpec::Report {
+                    code: fight.code,
+                    fight_id: fight.fight_id,
+                })
+                .collect::<Vec<_>>();
+            render_batch(client, &specs, video).await
+        }
+        None => Ok(()),
+    }
+}
+
+fn snapshot_pull(
+    pull: &Pull,
+    mut timestamps: Vec<f64>,
+    every_cast: Option<i64>,
+    trail: f64,
+    size: u32,
+    interpolation: Interpolation,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(ability_id) = every_cast {
+        timestamps.extend(
+            pull.cast_times(ability_id)
+                .map(|time| time - pull.fight.start_time),
+        );
+    }
+    if timestamps.is_empty() {
+        return Err("nothing to snapshot; pass --at and/or --every-cast".into());
+    }
+    timestamps.sort_by(f64::total_cmp);
+
+    render_snapshots(
+        &OverlaySource::from_pull(pull, interpolation),
+        &timestamps,
+        trail * 1000.0,
+        size,
+        output,
+    )
+}
+
+async fn export_viewer(
+    client: &Client,
+    spec: &PullSpec,
+    output: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let pull = load_pulls(client, std::slice::from_ref(spec))
+        .await?
+        .remove(0);
+    let output = output
+        .unwrap_or_else(|| PathBuf::from(format!("output/{}_{}.html", pull.code, pull.fight.id)));
+
+    viewer::export_viewer(&pull, output)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
 
-    let args = std::env::args().collect::<Vec<_>>();
+    let cli = Cli::parse();
 
-    let api_token = std::env::var("FFLOGS_API_TOKEN").expect("Missing API access token");
-    let client = Client::new(&api_token)?;
+    let client = if cli.offline || !cli.command.needs_api() {
+        Client::offline()
+    } else {
+        let api_token = std::env::var("FFLOGS_API_TOKEN").map_err(|_| {
+            "FFLOGS_API_TOKEN isn't set; it's needed to query FF Logs (or pass --offline and \
+             --cache-dir to work from cached responses)"
+        })?;
+        Client::new(&api_token)?
+    };
+    let client = match cli.cache_dir {
+        Some(cache_dir) => client.with_cache_dir(cache_dir),
+        None => client,
+    };
+    let client = client.with_rate_limit(
+        cli.rate_limit_threshold,
+        if cli.fail_on_rate_limit {
+            rate_limit::OnLowBudget::Fail
+        } else {
+            rate_limit::OnLowBudget::Wait
+        },
+    );
 
-    // let response = client
-    //     .query::<queries::IndividualCharacter>(queries::individual_character::Variables {
-    //         id: MY_CHARACTER_ID,
-    //         encounter_id: P5S_ENCOUNTER_ID,
-    //     })
-    //     .await?;
+    match cli.command {
+        Command::Render {
+            code,
+            fight,
+            damage,
+            video,
+        } => read_report(&client, &code, fight, &damage, &video).await?,
+        Command::Fetch { pull, output } => {
+            let pull = load_pulls(&client, std::slice::from_ref(&pull))
+                .await?
+                .remove(0);
+            let output = output.unwrap_or_else(|| {
+                PathBuf::from(format!("output/{}_{}.json", pull.code, pull.fight.id))
+            });
+            pull_file::save(&pull, &output)?
+        }
+        Command::Overlay {
+            pulls,
+            anchor,
+            video,
+        } => overlay_pulls(&client, &pulls, anchor.unwrap_or(Anchor::PullStart), &video).await?,
+        Command::Grid {
+            pulls,
+            anchor,
+            columns,
+            cell_size,
+            video,
+        } => {
+            grid_pulls(
+                &client,
+                &pulls,
+                anchor.unwrap_or(Anchor::PullStart),
+                columns,
+                cell_size,
+                &video,
+            )
+            .await?
+        }
+        Command::Discover {
+            guild,
+            server,
+            region,
+            guild_id,
+            user_id,
+            since,
+            until,
+            zone,
+            encounter,
+            kills_only,
+            render,
+            video,
+        } => {
+            let owner = match (guild, server, region, guild_id, user_id) {
+                (Some(name), Some(server), Some(region), _, _) => ReportOwner::Guild {
+                    name,
+                    server,
+                    region,
+                },
+                (_, _, _, Some(id), _) => ReportOwner::GuildId(id),
+                (_, _, _, _, Some(id)) => ReportOwner::User(id),
+                // clap enforces one of the three
+                _ => unreachable!(),
+            };
+            discover_fights(
+                &client,
+                &owner,
+                since,
+                until.unwrap_or_else(SystemTime::now),
+                zone,
+                encounter.as_ref(),
+                kills_only,
+                render.then_some(&video),
+            )
+            .await?
+        }
+        Command::Character {
+            id,
+            name,
+            server,
+            region,
+            encounter,
+            limit,
+            overlay,
+            anchor,
+            video,
+        } => {
+            let character = match (id, name, server, region) {
+                (Some(id), _, _, _) => CharacterRef::Id(id),
+                (None, Some(name), Some(server), Some(region)) => CharacterRef::Name {
+                    name,
+                    server,
+                    region,
+                },
+                // clap enforces one or the other
+                _ => unreachable!(),
+            };
+            render_character(
+                &client,
+                &character,
+                &encounter,
+                limit,
+                overlay.then_some(anchor.unwrap_or(Anchor::PullStart)),
+                &video,
+            )
+            .await?
+        }
+        Command::Rankings {
+            encounter,
+            limit,
+            overlay,
+            anchor,
+            video,
+        } => {
+            render_rankings(
+                &client,
+                &encounter,
+                limit,
+                overlay.then_some(anchor.unwrap_or(Anchor::PullStart)),
+                &video,
+            )
+            .await?
+        }
+        Command::Zones { expansion } => {
+            for zone in encounters::list_zones(&client, expansion).await? {
+                println!(
+                    "{}: {} ({}){}",
+                    zone.id,
+                    zone.name,
+                    zone.expansion,
+                    if zone.frozen { ", frozen" } else { "" }
+                );
+                for (id, name) in &zone.encounters {
+                    println!("    {}: {}", id, name);
+                }
+            }
+        }
+        Command::Guilds {
+            server,
+            region,
+            page,
+        } => {
+            let (guilds, has_more_pages) =
+                discovery::list_guilds(&client, &server, &region, page).await?;
+            for (id, name) in &guilds {
+                println!("{}: {}", id, name);
+            }
+            if has_more_pages {
+                println!("More on --page {}", page + 1);
+            }
+        }
+        Command::Viewer { pull, output } => export_viewer(&client, &pull, output).await?,
+        Command::Snapshot {
+            pull,
+            at,
+            every_cast,
+            trail,
+            size,
+            output,
+            interpolation,
+        } => {
+            let pull = load_pulls(&client, std::slice::from_ref(&pull))
+                .await?
+                .remove(0);
+            snapshot_pull(&pull, at, every_cast, trail, size, interpolation, &output)?
+        }
+        Command::Deaths {
+            pull,
+            window,
+            size,
+            output,
+            interpolation,
+        } => {
+            let pull = load_pulls(&client, std::slice::from_ref(&pull))
+                .await?
+                .remove(0);
+            let recaps = deaths::death_recaps(&pull, window, interpolation);
+            deaths::write_recaps(&pull, &recaps, window, size, interpolation, &output)?
+        }
+        Command::Export {
+            pull,
+            rate,
+            tracks,
+            events,
+            interpolation,
+        } => {
+            if rate <= 0.0 {
+                return Err("--rate has to be positive".into());
+            }
+            let pull = load_pulls(&client, std::slice::from_ref(&pull))
+                .await?
+                .remove(0);
+            export::track_table(&pull, rate, interpolation).write(&tracks)?;
+            export::event_table(&pull).write(&events)?;
+        }
+        Command::Play {
+            pull,
+            interpolation,
+        } => {
+            let pull = load_pulls(&client, std::slice::from_ref(&pull))
+                .await?
+                .remove(0);
+            tui::play(&pull, interpolation)?
+        }
+        // Runs until killed, so there is no summary to print afterwards
+        Command::Serve { addr } => return server::serve(client, addr).await,
+    }
 
-    // // println!("{:#?}", response);
+    if let Some(budget) = client.rate_budget() {
+        println!(
+            "{:.0} of {} API points left this hour",
+            budget.points_remaining(),
+            budget.limit_per_hour
+        );
+    }
 
-    // let report_codes = get_report_codes(
-    //     &response
-    //         .character_data
-    //         .unwrap()
-    //         .character
-    //         .unwrap()
-    //         .encounter_rankings
-    //         .unwrap(),
-    // )
-    // .unwrap();
+    Ok(())
+}
 
-    // println!("{:#?}", report_codes);
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
 
-    read_report(&client, &args[1]).await?;
+    use super::Cli;
 
-    // let f = std::io::BufReader::new(std::fs::File::open("test.json").unwrap());
-    // let events: Vec<Event> = serde_json::from_reader(f).unwrap();
+    #[test]
+    fn cli_test() {
+        // Catches clashing argument names, which clap otherwise only reports when run
+        Cli::command().debug_assert();
+    }
 
-    // dbg!(events.len());
+    #[test]
+    fn needs_api_test() {
+        let needs_api = |args: &[&str]| {
+            Cli::try_parse_from(std::iter::once("ff_mechanic_viz").chain(args.iter().copied()))
+                .unwrap()
+                .command
+                .needs_api()
+        };
 
-    Ok(())
+        assert!(!needs_api(&["render", "pull.json"]));
+        assert!(!needs_api(&["overlay", "a.json", "b.log:2"]));
+        assert!(!needs_api(&["deaths", "Network_26001.log"]));
+        assert!(needs_api(&["render", "abc123"]));
+        assert!(needs_api(&["overlay", "a.json", "abc123:3"]));
+        assert!(needs_api(&["snapshot", "abc123:3", "--at", "10"]));
+    }
 }
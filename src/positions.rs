@@ -1,10 +1,42 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ops::Bound};
 
 use ordered_float::OrderedFloat;
 
 pub type Position = (f64, f64);
 pub type Rect = (Position, Position); // min, max
 
+// Players run at 6 yalms a second, and positions are in hundredths of a yalm; this is per millisecond
+const RUN_SPEED: f64 = 0.6;
+// Samples further apart than this leave too much room to guess where the actor went in between
+const LOW_CONFIDENCE_GAP: f64 = 5000.0;
+
+/// How to fill in positions between samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    // Stay at each sample until the next one
+    Step,
+    // Move in a straight line at a steady speed from one sample to the next
+    #[default]
+    Linear,
+    // Curve smoothly through the samples on either side
+    CatmullRom,
+    // Run towards the next sample at full speed, then wait there
+    MaxSpeed,
+}
+
+pub fn parse_interpolation(spec: &str) -> Result<Interpolation, String> {
+    match spec.to_ascii_lowercase().as_str() {
+        "step" => Ok(Interpolation::Step),
+        "linear" => Ok(Interpolation::Linear),
+        "catmull-rom" | "spline" => Ok(Interpolation::CatmullRom),
+        "max-speed" => Ok(Interpolation::MaxSpeed),
+        _ => Err(format!(
+            "unknown interpolation {:?}; use step, linear, catmull-rom or max-speed",
+            spec
+        )),
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct PositionHistory {
     history: BTreeMap<OrderedFloat<f64>, Position>,
@@ -58,6 +90,11 @@ impl PositionHistory {
 
     // Assumes linear motion over time between points. Panics if there's no entries.
     pub fn get_position_at(&self, timestamp: f64) -> Position {
+        self.position_at(timestamp, Interpolation::Linear)
+    }
+
+    // Panics if there's no entries.
+    pub fn position_at(&self, timestamp: f64, interpolation: Interpolation) -> Position {
         let timestamp = OrderedFloat(timestamp);
 
         // Simple case: there is an entry at the specified timestamp
@@ -71,7 +108,7 @@ impl PositionHistory {
             (*k, *v)
         });
         let (next_time, next_pos) = self.get_next_entry(timestamp).unwrap_or_else(|| {
-            let (k, v) = self.history.iter().rev().next().unwrap();
+            let (k, v) = self.history.iter().next_back().unwrap();
             (*k, *v)
         });
 
@@ -80,14 +117,104 @@ impl PositionHistory {
             return prev_pos;
         }
 
-        // Linearly interpolate between the two positions
-        let dt = next_time - prev_time;
-        let ratio = (timestamp - prev_time) / dt;
+        let (t, t1, t2) = (timestamp.0, prev_time.0, next_time.0);
+        match interpolation {
+            Interpolation::Step => prev_pos,
+            Interpolation::Linear => lerp(prev_pos, next_pos, (t - t1) / (t2 - t1)),
+            Interpolation::CatmullRom => {
+                // Without a sample beyond either end, mirror the segment so the curve stays put
+                let (t0, p0) = self
+                    .get_previous_entry(prev_time)
+                    .map_or((t1 - (t2 - t1), prev_pos), |(time, pos)| (time.0, pos));
+                let (t3, p3) = self
+                    .get_next_entry(next_time)
+                    .map_or((t2 + (t2 - t1), next_pos), |(time, pos)| (time.0, pos));
+
+                // Barry and Goldman's formulation, with the sample times as knots
+                let a1 = lerp(p0, prev_pos, (t - t0) / (t1 - t0));
+                let a2 = lerp(prev_pos, next_pos, (t - t1) / (t2 - t1));
+                let a3 = lerp(next_pos, p3, (t - t2) / (t3 - t2));
+                let b1 = lerp(a1, a2, (t - t0) / (t2 - t0));
+                let b2 = lerp(a2, a3, (t - t1) / (t3 - t1));
+                lerp(b1, b2, (t - t1) / (t2 - t1))
+            }
+            Interpolation::MaxSpeed => {
+                let distance = (next_pos.0 - prev_pos.0).hypot(next_pos.1 - prev_pos.1);
+                let travel_time = distance / RUN_SPEED;
+                if travel_time >= t2 - t1 {
+                    // Faster than running (a knockback or a teleport), so there's nothing to clamp
+                    lerp(prev_pos, next_pos, (t - t1) / (t2 - t1))
+                } else {
+                    lerp(prev_pos, next_pos, ((t - t1) / travel_time).min(1.0))
+                }
+            }
+        }
+    }
 
-        let dx = next_pos.0 - prev_pos.0;
-        let dy = next_pos.1 - prev_pos.1;
+    /// Whether there are samples close enough around `timestamp` to trust the position there.
+    pub fn is_confident_at(&self, timestamp: f64) -> bool {
+        let timestamp = OrderedFloat(timestamp);
+        if self.history.contains_key(&timestamp) {
+            return true;
+        }
 
-        (prev_pos.0 + (ratio.0 * dx), prev_pos.1 + (ratio.0 * dy))
+        let gap = match (
+            self.get_previous_entry(timestamp),
+            self.get_next_entry(timestamp),
+        ) {
+            // add_update collapses a run of identical samples into its two ends, so a long gap
+            // between equal positions is someone standing still, not missing data
+            (Some((_, prev_pos)), Some((_, next_pos))) if prev_pos == next_pos => return true,
+            (Some((prev_time, _)), Some((next_time, _))) => next_time.0 - prev_time.0,
+            // Before the first sample or after the last, only the distance to it counts
+            (Some((time, _)), None) | (None, Some((time, _))) => (timestamp.0 - time.0).abs(),
+            (None, None) => return false,
+        };
+        gap <= LOW_CONFIDENCE_GAP
+    }
+
+    /// Fills `buffer` with positions `step` milliseconds apart, starting at `start`.
+    pub fn resample_into(
+        &self,
+        start: f64,
+        step: f64,
+        interpolation: Interpolation,
+        buffer: &mut [Position],
+    ) {
+        for (i, position) in buffer.iter_mut().enumerate() {
+            *position = self.position_at(start + i as f64 * step, interpolation);
+        }
+    }
+
+    /// Every recorded (timestamp, position), oldest first.
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = (f64, Position)> + '_ {
+        self.history
+            .iter()
+            .map(|(time, position)| (time.0, *position))
+    }
+
+    /// The recorded samples from `start` to `end`, inclusive.
+    pub fn samples_between(
+        &self,
+        start: f64,
+        end: f64,
+    ) -> impl DoubleEndedIterator<Item = (f64, Position)> + '_ {
+        // An empty range rather than BTreeMap's panic when the ends are the wrong way around
+        let end = if end < start {
+            Bound::Excluded(OrderedFloat(start))
+        } else {
+            Bound::Included(OrderedFloat(end))
+        };
+        self.history
+            .range((Bound::Included(OrderedFloat(start)), end))
+            .map(|(time, position)| (time.0, *position))
+    }
+
+    /// Timestamps of the first and last samples.
+    pub fn time_bounds(&self) -> Option<(f64, f64)> {
+        let first = self.history.keys().next()?;
+        let last = self.history.keys().next_back()?;
+        Some((first.0, last.0))
     }
 
     pub fn len(&self) -> usize {
@@ -104,8 +231,7 @@ impl PositionHistory {
     ) -> Option<(OrderedFloat<f64>, Position)> {
         self.history
             .range(..timestamp)
-            .rev()
-            .next()
+            .next_back()
             .map(|(k, v)| (*k, *v))
     }
 
@@ -114,15 +240,22 @@ impl PositionHistory {
         timestamp: OrderedFloat<f64>,
     ) -> Option<(OrderedFloat<f64>, Position)> {
         self.history
-            .range(timestamp..)
+            .range((Bound::Excluded(timestamp), Bound::Unbounded))
             .next()
             .map(|(k, v)| (*k, *v))
     }
 }
 
+fn lerp(from: Position, to: Position, ratio: f64) -> Position {
+    (
+        from.0 + ratio * (to.0 - from.0),
+        from.1 + ratio * (to.1 - from.1),
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use super::PositionHistory;
+    use super::{Interpolation, PositionHistory};
 
     #[test]
     fn simple_add_test() {
@@ -151,8 +284,9 @@ mod tests {
         history.add_update(9, (20.0, 20.0));
         assert_eq!(history.len(), 4);
 
+        // 9 is now covered by 8 and 10.
         history.add_update(8, (20.0, 20.0));
-        assert_eq!(history.len(), 5);
+        assert_eq!(history.len(), 4);
     }
 
     #[test]
@@ -174,4 +308,116 @@ mod tests {
         assert_eq!(history.get_position_at(25.0), (5.0, 10.0));
         assert_eq!(history.get_position_at(28.0), (8.0, 10.0));
     }
+
+    #[test]
+    fn interpolation_test() {
+        let mut history = PositionHistory::default();
+
+        history.add_update(0, (0.0, 0.0));
+        history.add_update(1000, (0.0, 100.0));
+        history.add_update(2000, (100.0, 100.0));
+        history.add_update(3000, (100.0, 200.0));
+        // Far enough to take 3 seconds at full speed, but there's 10 to do it in
+        history.add_update(13000, (100.0, 2000.0));
+
+        let at = |time: f64, interpolation| history.position_at(time, interpolation);
+
+        assert_eq!(at(500.0, Interpolation::Step), (0.0, 0.0));
+        assert_eq!(at(1000.0, Interpolation::Step), (0.0, 100.0));
+        assert_eq!(at(12000.0, Interpolation::Step), (100.0, 200.0));
+
+        assert_eq!(at(1500.0, Interpolation::Linear), (50.0, 100.0));
+
+        // The curve passes through every sample, and carries on the way the neighbouring samples
+        // are headed: up from (0, 0), then on up towards (100, 200)
+        assert_eq!(at(2000.0, Interpolation::CatmullRom), (100.0, 100.0));
+        assert_eq!(at(1500.0, Interpolation::CatmullRom), (50.0, 100.0));
+        assert!(at(1250.0, Interpolation::CatmullRom).1 > 100.0);
+        assert!(at(1750.0, Interpolation::CatmullRom).1 < 100.0);
+        assert_eq!(at(-500.0, Interpolation::CatmullRom), (0.0, 0.0));
+
+        assert_eq!(at(4000.0, Interpolation::MaxSpeed), (100.0, 800.0));
+        assert_eq!(at(8000.0, Interpolation::MaxSpeed), (100.0, 2000.0));
+        // Moving 100 in a second is within running speed, so it's covered in the first 1/6 of it
+        assert_eq!(at(1500.0, Interpolation::MaxSpeed), (100.0, 100.0));
+
+        assert!(history.is_confident_at(1500.0));
+        assert!(history.is_confident_at(-4000.0));
+        assert!(!history.is_confident_at(8000.0));
+        assert!(!history.is_confident_at(20000.0));
+        assert!(history.is_confident_at(13000.0));
+    }
+
+    #[test]
+    fn stationary_confidence_test() {
+        // A sample every second while standing still for 10s, which add_update keeps as just the
+        // two ends of the run
+        let mut history = PositionHistory::default();
+        for second in 0..=10 {
+            history.add_update(second * 1000, (100.0, 100.0));
+        }
+        history.add_update(11000, (200.0, 100.0));
+        assert_eq!(history.samples().count(), 3);
+
+        assert!(history.is_confident_at(5000.0));
+        assert!(history.is_confident_at(10500.0));
+    }
+
+    #[test]
+    fn samples_test() {
+        let mut history = PositionHistory::default();
+        assert_eq!(history.time_bounds(), None);
+        assert_eq!(history.samples().count(), 0);
+
+        history.add_update(10, (0.0, 0.0));
+        history.add_update(20, (0.0, 10.0));
+        history.add_update(30, (10.0, 10.0));
+
+        assert_eq!(history.time_bounds(), Some((10.0, 30.0)));
+        assert_eq!(
+            history.samples().collect::<Vec<_>>(),
+            vec![
+                (10.0, (0.0, 0.0)),
+                (20.0, (0.0, 10.0)),
+                (30.0, (10.0, 10.0))
+            ]
+        );
+        assert_eq!(history.samples().next_back(), Some((30.0, (10.0, 10.0))));
+
+        assert_eq!(
+            history.samples_between(15.0, 30.0).collect::<Vec<_>>(),
+            vec![(20.0, (0.0, 10.0)), (30.0, (10.0, 10.0))]
+        );
+        assert_eq!(history.samples_between(21.0, 29.0).count(), 0);
+        assert_eq!(history.samples_between(30.0, 10.0).count(), 0);
+    }
+
+    #[test]
+    fn resample_test() {
+        let mut history = PositionHistory::default();
+
+        history.add_update(10, (0.0, 0.0));
+        history.add_update(20, (0.0, 10.0));
+        history.add_update(30, (10.0, 10.0));
+
+        let mut buffer = [(f64::NAN, f64::NAN); 6];
+        history.resample_into(5.0, 5.0, Interpolation::Linear, &mut buffer);
+        assert_eq!(
+            buffer,
+            [
+                (0.0, 0.0),
+                (0.0, 0.0),
+                (0.0, 5.0),
+                (0.0, 10.0),
+                (5.0, 10.0),
+                (10.0, 10.0)
+            ]
+        );
+
+        history.resample_into(10.0, 5.0, Interpolation::Step, &mut buffer[..3]);
+        assert_eq!(buffer[..3], [(0.0, 0.0), (0.0, 0.0), (0.0, 10.0)]);
+
+        // Nothing to fill
+        history.resample_into(10.0, 5.0, Interpolation::Linear, &mut []);
+    }
 }
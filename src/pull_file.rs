@@ -0,0 +1,215 @@
+use std::{collections::HashMap, error::Error, io::BufWriter, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::AbilityTable,
+    act_log,
+    events::Event,
+    report::{build_position_histories, FightSummary, Pull},
+    ActorInfo,
+};
+
+// Bumped whenever the layout changes in a way older builds can't read
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct PullFileRef<'a> {
+    version: u32,
+    code: &'a str,
+    fight: &'a FightSummary,
+    actors: &'a HashMap<i64, ActorInfo>,
+    abilities: &'a AbilityTable,
+    events: &'a [Event],
+}
+
+#[derive(Deserialize)]
+struct PullFile {
+    code: String,
+    fight: FightSummary,
+    actors: HashMap<i64, ActorInfo>,
+    abilities: AbilityTable,
+    events: Vec<Event>,
+}
+
+/// Whether a pull given on the command line names a local file rather than a report: either one
+/// saved by `fetch`, or an ACT network log.
+pub fn is_pull_file(spec: &str) -> bool {
+    let spec = spec.to_ascii_lowercase();
+    spec.ends_with(".json") || spec.ends_with(".log")
+}
+
+/// Saves everything needed to work on the pull without FF Logs: the fight (bounding box included),
+/// actors, ability names and the raw events.
+pub fn save(pull: &Pull, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    serde_json::to_writer(
+        BufWriter::new(std::fs::File::create(path)?),
+        &PullFileRef {
+            version: FORMAT_VERSION,
+            code: &pull.code,
+            fight: &pull.fight,
+            actors: &pull.actors,
+            abilities: &pull.abilities,
+            events: &pull.events,
+        },
+    )?;
+    println!("Saved {} to {}", pull.label(), path.display());
+
+    Ok(())
+}
+
+/// Loads the pull with `fight_id` from a file, or its only pull when no fight is given.
+pub fn load(path: &Path, fight_id: Option<i64>) -> Result<Pull, Box<dyn Error>> {
+    select(path, load_all(path)?, fight_id)
+}
+
+/// Every pull in a file: ACT logs can hold any number of them, saved pulls only ever one.
+pub fn load_all(path: &Path) -> Result<Vec<Pull>, Box<dyn Error>> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("log"))
+    {
+        act_log::load(path)
+    } else {
+        Ok(vec![load_saved(path)?])
+    }
+}
+
+/// Picks the pull with `fight_id` out of the ones loaded from `path`, or the only one.
+pub fn select(
+    path: &Path,
+    mut pulls: Vec<Pull>,
+    fight_id: Option<i64>,
+) -> Result<Pull, Box<dyn Error>> {
+    let fight_ids = || {
+        pulls
+            .iter()
+            .map(|pull| pull.fight.id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let index = match fight_id {
+        Some(fight_id) => pulls
+            .iter()
+            .position(|pull| pull.fight.id == fight_id)
+            .ok_or_else(|| {
+                format!(
+                    "{} has no fight {} (it holds {})",
+                    path.display(),
+                    fight_id,
+                    fight_ids()
+                )
+            })?,
+        None if pulls.len() == 1 => 0,
+        None => {
+            return Err(format!(
+                "{} holds fights {}; pick one as {}:FIGHT_ID",
+                path.display(),
+                fight_ids(),
+                path.display()
+            )
+            .into())
+        }
+    };
+    Ok(pulls.swap_remove(index))
+}
+
+fn load_saved(path: &Path) -> Result<Pull, Box<dyn Error>> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+
+    let header: Header = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("{} isn't a saved pull: {}", path.display(), e))?;
+    if header.version != FORMAT_VERSION {
+        return Err(format!(
+            "{} is a version {} pull file, but this build reads version {}",
+            path.display(),
+            header.version,
+            FORMAT_VERSION
+        )
+        .into());
+    }
+
+    let file: PullFile = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("{} isn't a saved pull: {}", path.display(), e))?;
+    Ok(Pull {
+        code: file.code,
+        fight: file.fight,
+        actors: file.actors,
+        abilities: file.abilities,
+        positions: build_position_histories(&file.events),
+        events: file.events,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::test_util::{actor, pull_with};
+
+    use super::{is_pull_file, load, save, select};
+
+    #[test]
+    fn pull_file_round_trip_test() {
+        let mut pull = pull_with(
+            [(1, actor("Tank", "Player", "Paladin"))],
+            serde_json::json!([
+                {"type": "damage", "abilityGameID": 31000, "amount": 19000, "hitType": 1, "sourceID": 100, "targetID": 1, "timestamp": 1600,
+                 "targetResources": {"hitPoints": 1000, "maxHitPoints": 2000, "mp": 10000, "x": 100, "y": 200, "facing": 0}},
+                {"type": "death", "sourceID": -1, "targetID": 1, "timestamp": 6500}
+            ]),
+        );
+        pull.fight.id = 3;
+        pull.fight.fight_percentage = Some(42.5);
+        // Positions aren't saved, so start without any to see them rebuilt
+        pull.positions.clear();
+
+        let path = std::env::temp_dir().join(format!("pull_file_test_{}.json", std::process::id()));
+        save(&pull, &path).unwrap();
+        let loaded = load(&path, Some(3)).unwrap();
+        assert!(load(&path, Some(4)).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(is_pull_file(&path.to_string_lossy()));
+        assert!(is_pull_file("Network_26001_20240302.log"));
+        assert!(!is_pull_file("abc123:3"));
+        assert_eq!(loaded.code, "abc");
+        assert_eq!(loaded.fight.id, 3);
+        assert_eq!(loaded.fight.fight_percentage, Some(42.5));
+        assert_eq!(loaded.actors[&1].subtype, "Paladin");
+        assert_eq!(loaded.events.len(), 2);
+        // Positions aren't saved; they're rebuilt from the events
+        assert!(loaded.positions.contains_key(&1));
+    }
+
+    #[test]
+    fn select_test() {
+        let path = std::path::Path::new("pulls.log");
+        let pulls = || {
+            (1..=3)
+                .map(|id| {
+                    let mut pull = pull_with([], serde_json::json!([]));
+                    pull.fight.id = id;
+                    pull
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(select(path, pulls(), Some(2)).unwrap().fight.id, 2);
+        assert!(select(path, pulls(), Some(4)).is_err());
+        // Without a fight, only a file with a single pull is unambiguous
+        assert!(select(path, pulls(), None).is_err());
+        assert_eq!(
+            select(path, pulls().split_off(2), None).unwrap().fight.id,
+            3
+        );
+    }
+}
@@ -1,138 +1,56 @@
 use graphql_client::GraphQLQuery;
 
-use crate::{client::{RateLimitableQuery, RateLimitInfo}, events};
+use crate::{
+    client::{RateLimitInfo, RateLimitableQuery},
+    events,
+};
 
+#[allow(clippy::upper_case_acronyms)]
 type JSON = serde_json::Value;
 #[allow(non_camel_case_types)]
 type EVENTS_JSON = Vec<events::Event>;
 
-// #[derive(GraphQLQuery)]
-// #[graphql(
-//     schema_path = "queries/schema.json",
-//     query_path = "queries.graphql",
-//     response_derives = "Debug"
-// )]
-// pub struct Regions;
-// impl RateLimitableQuery for Regions {
-//     fn get_rate_limit_data(response: &regions::ResponseData) -> Option<RateLimitInfo> {
-//         if let Some(data) = response.rate_limit.rate_limit_data.as_ref() {
-//             Some(RateLimitInfo {
-//                 limit_per_hour: data.limit_per_hour,
-//                 points_spent_this_hour: data.points_spent_this_hour,
-//                 points_reset_in: data.points_reset_in,
-//             })
-//         } else {
-//             None
-//         }
-//     }
-// }
-
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "queries/schema.json",
-    query_path = "queries/character.graphql",
-    response_derives = "Debug"
-)]
-pub struct IndividualCharacter;
-impl RateLimitableQuery for IndividualCharacter {
-    fn get_rate_limit_data(response: &individual_character::ResponseData) -> Option<RateLimitInfo> {
-        if let Some(data) = response.rate_limit.rate_limit_data.as_ref() {
-            Some(RateLimitInfo {
-                limit_per_hour: data.limit_per_hour,
-                points_spent_this_hour: data.points_spent_this_hour,
-                points_reset_in: data.points_reset_in,
-            })
-        } else {
-            None
+/// Declares a query from one of the .graphql files along with its `RateLimitableQuery` impl. Every
+/// query has to include the `rateLimit` fragment; `$module` is the snake_case module that
+/// graphql_client generates for it.
+// rustfmt mangles the indentation of attributes inside macro definitions
+#[rustfmt::skip]
+macro_rules! rate_limited_query {
+    ($name:ident, $module:ident, $path:literal) => {
+        #[derive(GraphQLQuery)]
+        #[graphql(
+            schema_path = "queries/schema.json",
+            query_path = $path,
+            response_derives = "Debug"
+        )]
+        pub struct $name;
+        impl RateLimitableQuery for $name {
+            fn get_rate_limit_data(response: &$module::ResponseData) -> Option<RateLimitInfo> {
+                response
+                    .rate_limit
+                    .rate_limit_data
+                    .as_ref()
+                    .map(|data| RateLimitInfo {
+                        limit_per_hour: data.limit_per_hour,
+                        points_spent_this_hour: data.points_spent_this_hour,
+                        points_reset_in: data.points_reset_in,
+                    })
+            }
         }
-    }
+    };
 }
 
-
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "queries/schema.json",
-    query_path = "queries/fights.graphql",
-    response_derives = "Debug"
-)]
-pub struct ReportFights;
-impl RateLimitableQuery for ReportFights {
-    fn get_rate_limit_data(response: &report_fights::ResponseData) -> Option<RateLimitInfo> {
-        if let Some(data) = response.rate_limit.rate_limit_data.as_ref() {
-            Some(RateLimitInfo {
-                limit_per_hour: data.limit_per_hour,
-                points_spent_this_hour: data.points_spent_this_hour,
-                points_reset_in: data.points_reset_in,
-            })
-        } else {
-            None
-        }
-    }
-}
-
-
-
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "queries/schema.json",
-    query_path = "queries/report.graphql",
-    response_derives = "Debug"
-)]
-pub struct ReportEvents;
-impl RateLimitableQuery for ReportEvents {
-    fn get_rate_limit_data(response: &report_events::ResponseData) -> Option<RateLimitInfo> {
-        if let Some(data) = response.rate_limit.rate_limit_data.as_ref() {
-            Some(RateLimitInfo {
-                limit_per_hour: data.limit_per_hour,
-                points_spent_this_hour: data.points_spent_this_hour,
-                points_reset_in: data.points_reset_in,
-            })
-        } else {
-            None
-        }
-    }
-}
-
-
-
-// #[derive(GraphQLQuery)]
-// #[graphql(
-//     schema_path = "schema.json",
-//     query_path = "queries.graphql",
-//     response_derives = "Debug"
-// )]
-// pub struct FightRankings;
-// impl RateLimitableQuery for FightRankings {
-//     fn get_rate_limit_data(response: &fight_rankings::ResponseData) -> Option<RateLimitInfo> {
-//         if let Some(data) = response.rate_limit.rate_limit_data.as_ref() {
-//             Some(RateLimitInfo {
-//                 limit_per_hour: data.limit_per_hour,
-//                 points_spent_this_hour: data.points_spent_this_hour,
-//                 points_reset_in: data.points_reset_in,
-//             })
-//         } else {
-//             None
-//         }
-//     }
-// }
-
-// #[derive(GraphQLQuery)]
-// #[graphql(
-//     schema_path = "schema.json",
-//     query_path = "queries.graphql",
-//     response_derives = "Debug"
-// )]
-// pub struct Guilds;
-// impl RateLimitableQuery for Guilds {
-//     fn get_rate_limit_data(response: &guilds::ResponseData) -> Option<RateLimitInfo> {
-//         if let Some(data) = response.rate_limit.rate_limit_data.as_ref() {
-//             Some(RateLimitInfo {
-//                 limit_per_hour: data.limit_per_hour,
-//                 points_spent_this_hour: data.points_spent_this_hour,
-//                 points_reset_in: data.points_reset_in,
-//             })
-//         } else {
-//             None
-//         }
-//     }
-// }
\ No newline at end of file
+rate_limited_query!(
+    IndividualCharacter,
+    individual_character,
+    "queries/character.graphql"
+);
+rate_limited_query!(ReportFights, report_fights, "queries/fights.graphql");
+rate_limited_query!(ReportEvents, report_events, "queries/report.graphql");
+
+rate_limited_query!(Encounters, encounters, "queries/world.graphql");
+rate_limited_query!(Zones, zones, "queries/world.graphql");
+rate_limited_query!(FightRankings, fight_rankings, "queries/world.graphql");
+rate_limited_query!(Guilds, guilds, "queries/guild.graphql");
+rate_limited_query!(Guild, guild, "queries/guild.graphql");
+rate_limited_query!(Reports, reports, "queries/reports.graphql");
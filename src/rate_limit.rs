@@ -0,0 +1,201 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::client::RateLimitInfo;
+
+/// Don't let the hourly budget drop below this many points unless told otherwise.
+pub const DEFAULT_THRESHOLD: f64 = 100.0;
+
+// Assumed cost of a query type we haven't seen a response for yet
+const DEFAULT_QUERY_COST: f64 = 1.0;
+
+/// What to do when a query would take the budget below the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnLowBudget {
+    // Hold queries back until the hourly budget resets
+    Wait,
+    // Fail the query instead
+    Fail,
+}
+
+/// The hourly API budget as last reported by FF Logs, plus the estimated cost of queries sent since.
+#[derive(Debug, Clone, Copy)]
+pub struct RateBudget {
+    pub limit_per_hour: f64,
+    pub points_spent: f64,
+    pub reset_at: Instant,
+}
+impl RateBudget {
+    pub fn points_remaining(&self) -> f64 {
+        (self.limit_per_hour - self.points_spent).max(0.0)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    budget: Option<RateBudget>,
+    // pointsSpentThisHour from the latest response, to work out what each query cost
+    last_reported_spent: Option<f64>,
+    // operation name -> (total points, responses)
+    costs: HashMap<&'static str, (f64, u32)>,
+}
+impl State {
+    // Once the hour is up we know nothing about the new budget until the next response.
+    fn expire(&mut self) {
+        if self
+            .budget
+            .is_some_and(|budget| budget.reset_at <= Instant::now())
+        {
+            self.budget = None;
+            self.last_reported_spent = None;
+        }
+    }
+
+    // Average points a query of this type has cost so far
+    fn estimated_cost(&self, operation: &str) -> f64 {
+        match self.costs.get(operation) {
+            Some((total, count)) => total / *count as f64,
+            None => DEFAULT_QUERY_COST,
+        }
+    }
+}
+
+/// Keeps track of the API budget across every query a client sends, including concurrent ones.
+pub struct RateLimiter {
+    threshold: f64,
+    on_low: OnLowBudget,
+    state: Mutex<State>,
+}
+impl RateLimiter {
+    pub fn new(threshold: f64, on_low: OnLowBudget) -> Self {
+        RateLimiter {
+            threshold,
+            on_low,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn budget(&self) -> Option<RateBudget> {
+        let mut state = self.state.lock().unwrap();
+        state.expire();
+        state.budget
+    }
+
+    /// Waits until the budget has room for another `operation` (or fails, depending on the mode)
+    /// and counts its estimated cost against the budget.
+    pub async fn reserve(&self, operation: &'static str) -> Result<(), String> {
+        loop {
+            let (points_remaining, reset_at) = {
+                let mut state = self.state.lock().unwrap();
+                state.expire();
+                let cost = state.estimated_cost(operation);
+
+                match &mut state.budget {
+                    Some(budget) if budget.points_remaining() - cost < self.threshold => {
+                        (budget.points_remaining(), budget.reset_at)
+                    }
+                    Some(budget) => {
+                        budget.points_spent += cost;
+                        return Ok(());
+                    }
+                    None => return Ok(()),
+                }
+            };
+
+            let time_to_wait = reset_at.saturating_duration_since(Instant::now());
+            if self.on_low == OnLowBudget::Fail {
+                return Err(format!(
+                    "rate limit nearly used up ({:.0} points left, resets in {:?})",
+                    points_remaining, time_to_wait
+                ));
+            }
+
+            println!(
+                "Rate limit nearly used up ({:.0} points left), waiting {:?} for it to reset",
+                points_remaining, time_to_wait
+            );
+            tokio::time::sleep_until(reset_at).await;
+        }
+    }
+
+    /// Records the budget reported alongside a response to `operation`.
+    pub fn update(&self, operation: &'static str, info: &RateLimitInfo) {
+        let mut state = self.state.lock().unwrap();
+
+        // Responses only carry the hour's running total, so whatever it went up by since the
+        // previous response is what this query cost. With queries in flight concurrently that's
+        // only roughly true, but it evens out in the average.
+        if let Some(previous) = state.last_reported_spent {
+            let cost = info.points_spent_this_hour - previous;
+            if cost >= 0.0 {
+                let entry = state.costs.entry(operation).or_default();
+                entry.0 += cost;
+                entry.1 += 1;
+            }
+        }
+        state.last_reported_spent = Some(info.points_spent_this_hour);
+
+        let budget = RateBudget {
+            limit_per_hour: info.limit_per_hour as f64,
+            points_spent: info.points_spent_this_hour,
+            reset_at: Instant::now() + Duration::from_secs(info.points_reset_in.max(0) as u64),
+        };
+        state.budget = Some(budget);
+
+        println!(
+            "{} cost ~{:.1} points, {:.0} of {} left this hour",
+            operation,
+            state.estimated_cost(operation),
+            budget.points_remaining(),
+            info.limit_per_hour
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OnLowBudget, RateLimiter, DEFAULT_QUERY_COST};
+    use crate::client::RateLimitInfo;
+
+    fn info(points_spent_this_hour: f64) -> RateLimitInfo {
+        RateLimitInfo {
+            limit_per_hour: 1000,
+            points_spent_this_hour,
+            points_reset_in: 3600,
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_test() {
+        let limiter = RateLimiter::new(100.0, OnLowBudget::Fail);
+        assert!(limiter.budget().is_none());
+        assert_eq!(
+            limiter.state.lock().unwrap().estimated_cost("ReportEvents"),
+            DEFAULT_QUERY_COST
+        );
+
+        limiter.reserve("ReportEvents").await.unwrap();
+        limiter.update("ReportEvents", &info(500.0));
+        limiter.reserve("ReportEvents").await.unwrap();
+        limiter.update("ReportEvents", &info(510.0));
+        limiter.reserve("ReportEvents").await.unwrap();
+        limiter.update("ReportEvents", &info(530.0));
+        assert_eq!(
+            limiter.state.lock().unwrap().estimated_cost("ReportEvents"),
+            15.0
+        );
+        assert_eq!(
+            limiter.state.lock().unwrap().estimated_cost("ReportFights"),
+            DEFAULT_QUERY_COST
+        );
+
+        // Reservations count against the budget before the response comes back
+        limiter.reserve("ReportEvents").await.unwrap();
+        assert_eq!(limiter.budget().unwrap().points_remaining(), 455.0);
+
+        limiter.update("ReportEvents", &info(890.0));
+        assert!(limiter.reserve("ReportFights").await.is_ok());
+        assert!(limiter.reserve("ReportEvents").await.is_err());
+    }
+}
@@ -0,0 +1,658 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::PathBuf,
+    time::Duration,
+};
+
+use futures::{StreamExt, TryStreamExt};
+use humantime::format_duration;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::{AbilityInfo, AbilityTable},
+    client::Client,
+    events::Event,
+    positions::{PositionHistory, Rect},
+    queries, ActorInfo,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FightSummary {
+    pub id: i64,
+    pub encounter_id: i64,
+    // Encounter name, e.g. "Hephaistos"
+    pub name: String,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub bounding_box: Rect,
+    pub kill: bool,
+    pub fight_percentage: Option<f64>,
+    pub enemies: Vec<String>,
+}
+impl FightSummary {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis((self.end_time - self.start_time) as u64)
+    }
+
+    pub fn outcome_text(&self) -> String {
+        if self.kill {
+            format!("killed in {}", format_duration(self.duration()))
+        } else {
+            format!(
+                "wiped at {}% after {}",
+                self.fight_percentage.unwrap_or(100.0),
+                format_duration(self.duration())
+            )
+        }
+    }
+}
+
+/// Where to get a pull from: a fight in an FF Logs report, or a local file (saved by `fetch`, or an
+/// ACT log). Files holding several pulls need the fight picked out of them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PullSpec {
+    Report {
+        code: String,
+        fight_id: i64,
+    },
+    File {
+        path: PathBuf,
+        fight_id: Option<i64>,
+    },
+}
+
+pub struct Report {
+    pub code: String,
+    pub actors: HashMap<i64, ActorInfo>,
+    pub abilities: AbilityTable,
+    pub fights: Vec<FightSummary>,
+}
+impl Report {
+    pub fn get_fight(&self, fight_id: i64) -> Option<&FightSummary> {
+        self.fights.iter().find(|fight| fight.id == fight_id)
+    }
+}
+
+/// Where to put time zero when lining several pulls up against each other.
+#[derive(Debug, Clone, Copy)]
+pub enum Anchor {
+    PullStart,
+    // The `occurrence`th (1-based) cast of the given ability by anyone in the fight.
+    Cast { ability_id: i64, occurrence: usize },
+}
+
+/// A single fight with all of its events loaded and processed.
+pub struct Pull {
+    pub code: String,
+    pub fight: FightSummary,
+    pub actors: HashMap<i64, ActorInfo>,
+    pub abilities: AbilityTable,
+    pub events: Vec<Event>,
+    pub positions: HashMap<i64, PositionHistory>,
+}
+impl Pull {
+    pub fn label(&self) -> String {
+        format!("{} #{}", self.code, self.fight.id)
+    }
+
+    /// Timestamps of every completed cast of the given ability, by anyone in the fight.
+    pub fn cast_times(&self, ability_id: i64) -> impl Iterator<Item = f64> + '_ {
+        self.events.iter().filter_map(move |event| match event {
+            Event::Cast {
+                ability_game_id,
+                timestamp,
+                ..
+            } if *ability_game_id == ability_id => Some(*timestamp as f64),
+            _ => None,
+        })
+    }
+
+    pub fn anchor_time(&self, anchor: Anchor) -> Option<f64> {
+        match anchor {
+            Anchor::PullStart => Some(self.fight.start_time),
+            Anchor::Cast {
+                ability_id,
+                occurrence,
+            } => self.cast_times(ability_id).nth(occurrence.checked_sub(1)?),
+        }
+    }
+}
+
+/// Default name for rendered videos, see [`output_file_name`].
+pub const DEFAULT_OUTPUT_NAME: &str = "{code}_{fight}_{boss}_{kill}.mp4";
+
+// Keeps names like "Hephaistos II" or "The Omega Protocol (Ultimate)" usable as file names.
+fn file_name_part(text: &str) -> String {
+    let mut result = String::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if !word.is_empty() {
+            if !result.is_empty() {
+                result.push('-');
+            }
+            result.push_str(word);
+        }
+    }
+    result
+}
+
+/// Fills in a file name template for the given pulls. `{code}` and `{fight}` are the report codes
+/// and fight IDs, `{boss}` is the encounter name and `{kill}` is "kill" or "wipe" ("mixed" if the
+/// pulls disagree). Several pulls have their codes and fight IDs joined with "+".
+pub fn output_file_name(template: &str, pulls: &[Pull]) -> String {
+    let mut codes: Vec<&str> = Vec::new();
+    for pull in pulls {
+        if !codes.contains(&pull.code.as_str()) {
+            codes.push(&pull.code);
+        }
+    }
+    let fights = pulls
+        .iter()
+        .map(|pull| pull.fight.id.to_string())
+        .collect::<Vec<_>>();
+    let boss = pulls
+        .first()
+        .map(|pull| file_name_part(&pull.fight.name))
+        .unwrap_or_default();
+    let kill = if pulls.iter().all(|pull| pull.fight.kill) {
+        "kill"
+    } else if pulls.iter().all(|pull| !pull.fight.kill) {
+        "wipe"
+    } else {
+        "mixed"
+    };
+
+    template
+        .replace("{code}", &codes.join("+"))
+        .replace("{fight}", &fights.join("+"))
+        .replace("{boss}", &boss)
+        .replace("{kill}", kill)
+}
+
+/// Formats a time relative to the start of a fight like "4:32.5".
+pub fn format_fight_time(ms: f64) -> String {
+    let seconds = (ms / 1000.0).max(0.0);
+    format!("{}:{:04.1}", (seconds / 60.0) as u64, seconds % 60.0)
+}
+
+/// Parses "4:32.5" or "272.5" (seconds) into milliseconds since the start of a fight.
+pub fn parse_fight_time(text: &str) -> Result<f64, String> {
+    let (minutes, seconds) = match text.split_once(':') {
+        Some((minutes, seconds)) => (
+            minutes
+                .parse::<u64>()
+                .map_err(|e| format!("bad minutes in {:?}: {}", text, e))?,
+            seconds,
+        ),
+        None => (0, text),
+    };
+    let seconds = seconds
+        .parse::<f64>()
+        .map_err(|e| format!("bad seconds in {:?}: {}", text, e))?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(format!("invalid time {:?}", text));
+    }
+
+    Ok((minutes as f64 * 60.0 + seconds) * 1000.0)
+}
+
+pub async fn load_report(client: &Client, code: &str) -> Result<Report, Box<dyn Error>> {
+    let fight_data = client
+        .query::<queries::ReportFights>(queries::report_fights::Variables {
+            code: code.to_string(),
+        })
+        .await?
+        .report_data
+        .unwrap()
+        .report
+        .unwrap();
+
+    let actors = fight_data
+        .master_data
+        .as_ref()
+        .unwrap()
+        .actors
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|actor| {
+            let actor = actor.as_ref().unwrap();
+            let data = ActorInfo {
+                name: actor.name.as_ref().unwrap().clone(),
+                type_: actor.type_.as_ref().unwrap().clone(),
+                subtype: actor.sub_type.as_ref().unwrap().clone(),
+            };
+            (actor.id.unwrap(), data)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let fights = fight_data
+        .fights
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|fight| {
+            let fight = fight.as_ref().unwrap();
+
+            let mut enemies = fight
+                .enemy_np_cs
+                .as_ref()
+                .unwrap()
+                .iter()
+                .filter_map(|npc| {
+                    let id = npc.as_ref().unwrap().id.unwrap();
+                    let name = &actors.get(&id).as_ref().unwrap().name;
+                    if name == "Multiple Enemies" {
+                        None
+                    } else {
+                        Some(name.to_string())
+                    }
+                })
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            enemies.sort();
+
+            let bounding_box = fight.bounding_box.as_ref().unwrap();
+
+            FightSummary {
+                id: fight.id,
+                encounter_id: fight.encounter_id,
+                name: fight.name.clone(),
+                start_time: fight.start_time,
+                end_time: fight.end_time,
+                bounding_box: (
+                    (bounding_box.min_x as f64, bounding_box.min_y as f64),
+                    (bounding_box.max_x as f64, bounding_box.max_y as f64),
+                ),
+                kill: fight.kill.unwrap(),
+                fight_percentage: fight.fight_percentage,
+                enemies,
+            }
+        })
+        .collect();
+
+    let mut abilities = AbilityTable::default();
+    for ability in fight_data
+        .master_data
+        .as_ref()
+        .unwrap()
+        .abilities
+        .iter()
+        .flatten()
+        .flatten()
+    {
+        if let Some(game_id) = ability.game_id {
+            abilities.insert(
+                game_id as i64,
+                AbilityInfo {
+                    name: ability.name.clone().unwrap_or_default(),
+                    icon: ability.icon.clone().unwrap_or_default(),
+                    type_: ability.type_.clone().unwrap_or_default(),
+                },
+            );
+        }
+    }
+
+    Ok(Report {
+        code: code.to_string(),
+        actors,
+        abilities,
+        fights,
+    })
+}
+
+// Long fights are split into chunks of this many milliseconds that are paged through concurrently.
+const EVENT_CHUNK_DURATION: f64 = 120_000.0;
+const MAX_CONCURRENT_CHUNKS: usize = 4;
+
+// Pages through every event between `start_time` and `end_time`.
+async fn load_event_chunk(
+    client: &Client,
+    code: &str,
+    start_time: f64,
+    end_time: f64,
+    fight_id: i64,
+) -> Result<Vec<Event>, Box<dyn Error>> {
+    let mut result = Vec::new();
+
+    let mut page_start = start_time;
+    while page_start < end_time {
+        // The client retries transient failures, and always from the last page that succeeded, so
+        // a flaky request doesn't cost the pages fetched before it.
+        let events = client
+            .query::<queries::ReportEvents>(queries::report_events::Variables {
+                code: code.to_string(),
+                start_time: page_start,
+                end_time,
+                fight_ids: vec![fight_id],
+            })
+            .await
+            .map_err(|e| {
+                format!(
+                    "fetching events from {} failed after {} events: {}",
+                    page_start,
+                    result.len(),
+                    e
+                )
+            })?;
+
+        let mut report = events.report_data.unwrap().report.unwrap().events.unwrap();
+        let events = report.data.as_mut().unwrap();
+        result.append(events);
+
+        page_start = if let Some(page_start) = report.next_page_timestamp {
+            page_start
+        } else {
+            break;
+        };
+    }
+
+    Ok(result)
+}
+
+// Splits `start_time..end_time` into `EVENT_CHUNK_DURATION` sized pieces.
+fn event_chunks(start_time: f64, end_time: f64) -> Vec<(f64, f64)> {
+    let count = ((end_time - start_time) / EVENT_CHUNK_DURATION)
+        .ceil()
+        .max(1.0) as usize;
+    (0..count)
+        .map(|i| {
+            let chunk_start = start_time + i as f64 * EVENT_CHUNK_DURATION;
+            let chunk_end = if i + 1 == count {
+                end_time
+            } else {
+                chunk_start + EVENT_CHUNK_DURATION
+            };
+            (chunk_start, chunk_end)
+        })
+        .collect()
+}
+
+// Puts events from several chunks back together. Neighbouring chunks share their boundary
+// timestamp, so events at that instant can come back from both: the head of each chunk is checked
+// against the tail of the one before it, one match per event. Repeats anywhere else are real.
+fn merge_event_chunks(chunks: Vec<Vec<Event>>) -> Vec<Event> {
+    let mut result: Vec<Event> = Vec::new();
+    for chunk in chunks {
+        let boundary = result.last().map(Event::get_timestamp);
+        let tail_start = result
+            .iter()
+            .rposition(|event| Some(event.get_timestamp()) != boundary)
+            .map_or(0, |idx| idx + 1);
+        let tail_end = result.len();
+        let mut matched = vec![false; tail_end - tail_start];
+
+        let mut chunk = chunk.into_iter().peekable();
+        while let Some(event) = chunk.next_if(|event| Some(event.get_timestamp()) == boundary) {
+            let duplicate = (tail_start..tail_end)
+                .find(|&idx| !matched[idx - tail_start] && result[idx] == event);
+            match duplicate {
+                Some(idx) => matched[idx - tail_start] = true,
+                None => result.push(event),
+            }
+        }
+        result.extend(chunk);
+    }
+
+    result
+}
+
+/// Fetches every event of a fight. Long fights are fetched as several chunks at once, which the
+/// client's shared rate limit keeps in check.
+pub async fn load_all_events(
+    client: &Client,
+    code: &str,
+    start_time: f64,
+    end_time: f64,
+    fight_id: i64,
+) -> Result<Vec<Event>, Box<dyn Error>> {
+    let chunks = futures::stream::iter(event_chunks(start_time, end_time))
+        .map(|(chunk_start, chunk_end)| async move {
+            // Finished chunks wait in the stream until their turn, and Box<dyn Error> isn't Send.
+            load_event_chunk(client, code, chunk_start, chunk_end, fight_id)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .buffered(MAX_CONCURRENT_CHUNKS)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(merge_event_chunks(chunks))
+}
+
+pub fn build_position_histories(events: &[Event]) -> HashMap<i64, PositionHistory> {
+    // id -> position history
+    let mut position_history: HashMap<i64, PositionHistory> = HashMap::new();
+
+    for event in events {
+        let time = event.get_timestamp();
+
+        if let Some((id, res)) = event.get_source_resources() {
+            position_history
+                .entry(id)
+                .or_default()
+                .add_update(time, (res.x as f64, res.y as f64));
+        }
+
+        if let Some((id, res)) = event.get_target_resources() {
+            position_history
+                .entry(id)
+                .or_default()
+                .add_update(time, (res.x as f64, res.y as f64));
+        }
+    }
+
+    position_history
+}
+
+pub async fn load_pull(
+    client: &Client,
+    report: &Report,
+    fight_id: i64,
+) -> Result<Pull, Box<dyn Error>> {
+    let fight = report
+        .get_fight(fight_id)
+        .ok_or_else(|| format!("report {} has no fight {}", report.code, fight_id))?
+        .clone();
+
+    println!("Loading events for {} #{}...", report.code, fight.id);
+
+    let events = load_all_events(
+        client,
+        &report.code,
+        fight.start_time,
+        fight.end_time,
+        fight.id,
+    )
+    .await?;
+
+    println!(
+        "Loaded {} events! ({} events/second of fight)",
+        events.len(),
+        events.len() as f64 / fight.duration().as_secs_f64()
+    );
+
+    let positions = build_position_histories(&events);
+    println!(
+        "Tracked {} actors with {} position samples",
+        positions.len(),
+        positions.values().map(PositionHistory::len).sum::<usize>()
+    );
+
+    Ok(Pull {
+        code: report.code.clone(),
+        fight,
+        actors: report.actors.clone(),
+        abilities: report.abilities.clone(),
+        events,
+        positions,
+    })
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::collections::HashMap;
+
+    use super::{build_position_histories, FightSummary, Pull};
+    use crate::ActorInfo;
+
+    pub fn actor(name: &str, type_: &str, subtype: &str) -> ActorInfo {
+        ActorInfo {
+            name: name.to_string(),
+            type_: type_.to_string(),
+            subtype: subtype.to_string(),
+        }
+    }
+
+    /// Pull abc #1, a 10 second wipe on Hephaistos (encounter 86), made of `events` as FF Logs
+    /// returns them. Positions are built from the events, like a loaded pull's.
+    pub fn pull_with(
+        actors: impl IntoIterator<Item = (i64, ActorInfo)>,
+        events: serde_json::Value,
+    ) -> Pull {
+        let events: Vec<_> = serde_json::from_value(events).unwrap();
+        Pull {
+            code: "abc".to_string(),
+            fight: FightSummary {
+                id: 1,
+                encounter_id: 86,
+                name: "Hephaistos".to_string(),
+                start_time: 0.0,
+                end_time: 10000.0,
+                bounding_box: ((0.0, 0.0), (1.0, 1.0)),
+                kill: false,
+                fight_percentage: None,
+                enemies: Vec::new(),
+            },
+            actors: actors.into_iter().collect::<HashMap<_, _>>(),
+            abilities: Default::default(),
+            positions: build_position_histories(&events),
+            events,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        event_chunks, format_fight_time, merge_event_chunks, output_file_name, parse_fight_time,
+        test_util::pull_with, Pull,
+    };
+    use crate::events::Event;
+
+    fn pull(code: &str, id: i64, name: &str, kill: bool) -> Pull {
+        let mut pull = pull_with([], serde_json::json!([]));
+        pull.code = code.to_string();
+        pull.fight.id = id;
+        pull.fight.name = name.to_string();
+        pull.fight.kill = kill;
+        pull
+    }
+
+    #[test]
+    fn fight_time_test() {
+        assert_eq!(parse_fight_time("4:32.5"), Ok(272500.0));
+        assert_eq!(parse_fight_time("0:05"), Ok(5000.0));
+        assert_eq!(parse_fight_time("90"), Ok(90000.0));
+        assert!(parse_fight_time("4:xx").is_err());
+        assert!(parse_fight_time("-3").is_err());
+
+        assert_eq!(format_fight_time(272500.0), "4:32.5");
+        assert_eq!(format_fight_time(5000.0), "0:05.0");
+        assert_eq!(format_fight_time(-10.0), "0:00.0");
+    }
+
+    #[test]
+    fn output_file_name_test() {
+        let template = "{code}_{fight}_{boss}_{kill}.mp4";
+
+        assert_eq!(
+            output_file_name(template, &[pull("abc123", 4, "Hephaistos II", true)]),
+            "abc123_4_Hephaistos-II_kill.mp4"
+        );
+        assert_eq!(
+            output_file_name(
+                template,
+                &[
+                    pull("abc123", 4, "The Omega Protocol (Ultimate)", false),
+                    pull("abc123", 7, "The Omega Protocol (Ultimate)", true),
+                    pull("xyz789", 2, "The Omega Protocol (Ultimate)", false),
+                ]
+            ),
+            "abc123+xyz789_4+7+2_The-Omega-Protocol-Ultimate_mixed.mp4"
+        );
+        assert_eq!(
+            output_file_name(
+                "renders/{boss}/{fight}.gif",
+                &[pull("abc123", 1, "Kokytos", false)]
+            ),
+            "renders/Kokytos/1.gif"
+        );
+    }
+
+    #[test]
+    fn merge_event_chunks_test() {
+        assert_eq!(event_chunks(0.0, 60000.0), vec![(0.0, 60000.0)]);
+        assert_eq!(
+            event_chunks(1000.0, 250000.0),
+            vec![
+                (1000.0, 121000.0),
+                (121000.0, 241000.0),
+                (241000.0, 250000.0)
+            ]
+        );
+
+        let cast = |ability: i64, timestamp: i64| -> Event {
+            serde_json::from_str(&format!(
+                r#"{{"type": "cast", "abilityGameID": {}, "sourceID": 1, "targetID": 2, "timestamp": {}}}"#,
+                ability, timestamp
+            ))
+            .unwrap()
+        };
+
+        let merged = merge_event_chunks(vec![
+            vec![cast(1, 100), cast(2, 200), cast(3, 200)],
+            // The boundary events show up in both chunks, next to a distinct one at the same time
+            vec![cast(2, 200), cast(3, 200), cast(5, 200), cast(4, 300)],
+            vec![],
+            vec![cast(6, 400)],
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                cast(1, 100),
+                cast(2, 200),
+                cast(3, 200),
+                cast(5, 200),
+                cast(4, 300),
+                cast(6, 400)
+            ]
+        );
+
+        // Identical events inside one chunk (e.g. a multi-hit ability) are all kept, and only as
+        // many of them are dropped at the boundary as the previous chunk already had.
+        let merged = merge_event_chunks(vec![
+            vec![cast(1, 100), cast(1, 100), cast(7, 200), cast(7, 200)],
+            vec![
+                cast(7, 200),
+                cast(7, 200),
+                cast(7, 200),
+                cast(8, 300),
+                cast(8, 300),
+            ],
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                cast(1, 100),
+                cast(1, 100),
+                cast(7, 200),
+                cast(7, 200),
+                cast(7, 200),
+                cast(8, 300),
+                cast(8, 300)
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    error::Error,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+
+use crate::{client::Client, positions::Interpolation, report, video::render_animations, viewer};
+
+const INDEX_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Mechanic Visualizer</title></head>
+<body>
+<form action="/report" method="get">
+    <label>Report code <input name="code" autofocus></label>
+    <button type="submit">Open</button>
+</form>
+</body>
+</html>
+"#;
+
+// Tells apart the temporary files of renders running at the same time
+static RENDER_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Errors are reported to the browser as plain text; there's nobody else to tell.
+struct ServerError(StatusCode, String);
+impl From<Box<dyn Error>> for ServerError {
+    fn from(err: Box<dyn Error>) -> Self {
+        ServerError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    code: String,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Report codes end up in file paths, so only let through what FF Logs actually hands out.
+fn check_code(code: &str) -> Result<(), ServerError> {
+    if !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(())
+    } else {
+        Err(ServerError(
+            StatusCode::BAD_REQUEST,
+            format!("invalid report code {:?}", code),
+        ))
+    }
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX_PAGE)
+}
+
+async fn report_redirect(Query(query): Query<ReportQuery>) -> Result<Redirect, ServerError> {
+    // The code ends up in a Location header, so it has to be checked first
+    let code = query.code.trim();
+    check_code(code)?;
+    Ok(Redirect::to(&format!("/report/{}", code)))
+}
+
+async fn report_page(
+    State(client): State<Arc<Client>>,
+    Path(code): Path<String>,
+) -> Result<Html<String>, ServerError> {
+    check_code(&code)?;
+    let report = report::load_report(&client, &code).await?;
+
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>Report {0}</h1>\n<ul>\n",
+        escape_html(&code)
+    );
+    for fight in &report.fights {
+        writeln!(
+            page,
+            "<li>Fight {id} against {enemies} ({outcome}) &mdash; <a href=\"/report/{code}/{id}/viewer\">viewer</a> | <a href=\"/report/{code}/{id}/video\">video</a></li>",
+            id = fight.id,
+            enemies = escape_html(&fight.enemies.join(", ")),
+            outcome = escape_html(&fight.outcome_text()),
+            code = code,
+        )
+        .unwrap();
+    }
+    page.push_str("</ul>\n</body>\n</html>\n");
+
+    Ok(Html(page))
+}
+
+async fn viewer_page(
+    State(client): State<Arc<Client>>,
+    Path((code, fight_id)): Path<(String, i64)>,
+) -> Result<Html<String>, ServerError> {
+    check_code(&code)?;
+    let report = report::load_report(&client, &code).await?;
+    let pull = report::load_pull(&client, &report, fight_id).await?;
+
+    Ok(Html(viewer::render_viewer_html(&pull)?))
+}
+
+async fn video(
+    State(client): State<Arc<Client>>,
+    Path((code, fight_id)): Path<(String, i64)>,
+) -> Result<Response, ServerError> {
+    check_code(&code)?;
+
+    // Every fight renders to its own file, which is served as is from then on
+    let output = std::path::Path::new("output")
+        .join("serve")
+        .join(format!("{}_{}.mp4", code, fight_id));
+    if let Ok(video) = std::fs::read(&output) {
+        return Ok(([(header::CONTENT_TYPE, "video/mp4")], video).into_response());
+    }
+
+    let report = report::load_report(&client, &code).await?;
+    let pull = report::load_pull(&client, &report, fight_id).await?;
+
+    // Rendering is slow and entirely synchronous, so keep it off the async workers.
+    let video = tokio::task::spawn_blocking(move || {
+        // Render somewhere no other request is writing to, and only move the finished video into
+        // place, so concurrent requests for the same fight can't clobber each other's output.
+        let temp = output.with_file_name(format!(
+            "{}_{}.{}-{}.tmp.mp4",
+            code,
+            fight_id,
+            std::process::id(),
+            RENDER_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let rendered = render_animations(&pull, Interpolation::default(), (1024, 1024), &temp)
+            .map_err(|e| e.to_string())
+            .and_then(|()| std::fs::rename(&temp, &output).map_err(|e| e.to_string()));
+        if let Err(e) = rendered {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
+
+        std::fs::read(&output).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| ServerError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| ServerError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(([(header::CONTENT_TYPE, "video/mp4")], video).into_response())
+}
+
+pub fn router(client: Arc<Client>) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .route("/report", get(report_redirect))
+        .route("/report/:code", get(report_page))
+        .route("/report/:code/:fight/viewer", get(viewer_page))
+        .route("/report/:code/:fight/video", get(video))
+        .with_state(client)
+}
+
+pub async fn serve(client: Client, addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    println!("Listening on http://{}", addr);
+
+    axum::Server::bind(&addr)
+        .serve(router(Arc::new(client)).into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use super::router;
+    use crate::{client::Client, queries};
+
+    const REPORT_FIGHTS_RESPONSE: &str = r#"{"data": {
+        "reportData": {"report": {
+            "fights": [{
+                "enemyNPCs": [{"gameID": 1, "id": 10, "instanceCount": 1}],
+                "boundingBox": {"minX": 0, "maxX": 100, "minY": 0, "maxY": 100},
+                "startTime": 0.0, "endTime": 60000.0, "fightPercentage": 42.5, "kill": false,
+                "encounterID": 86, "name": "Hephaistos", "id": 3
+            }],
+            "masterData": {"actors": [
+                {"gameID": 1, "id": 10, "name": "Hephaistos", "type": "NPC", "subType": "Boss"}
+            ]}
+        }},
+        "rateLimitData": null
+    }}"#;
+
+    #[tokio::test]
+    async fn report_page_from_cache_test() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("ff_viz_serve_test_{}", std::process::id()));
+        let client = Client::offline().with_cache_dir(&cache_dir);
+        client
+            .seed_cache::<queries::ReportFights>(
+                queries::report_fights::Variables {
+                    code: "abc123".to_string(),
+                },
+                REPORT_FIGHTS_RESPONSE,
+            )
+            .unwrap();
+
+        let app = router(Arc::new(client));
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/report/abc123").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Fight 3 against Hephaistos (wiped at 42.5% after 1m)"));
+        assert!(body.contains("/report/abc123/3/viewer"));
+
+        // Not cached, so the offline client has to refuse.
+        let response = app
+            .oneshot(Request::get("/report/zzz999").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn report_redirect_test() {
+        let app = router(Arc::new(Client::offline()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/report?code=+abc123+")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection());
+        assert_eq!(response.headers()["location"], "/report/abc123");
+
+        for code in ["%2F%2Fevil.example", "abc%0D%0Ajunk", ""] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get(format!("/report?code={}", code))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", code);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    error::Error,
+    io::{Stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::{self, Color},
+    terminal,
+};
+
+use crate::{
+    positions::Interpolation,
+    report::{format_fight_time, Pull},
+    video::{job_color, overlay_time_range, sample_players, OverlaySource, FRAME_DURATION},
+};
+
+const SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+const SEEK_STEP: f64 = 5000.0;
+
+// Rows kept free below the arena for the status and help lines
+const STATUS_ROWS: u16 = 3;
+
+/// A grid of braille characters, each holding 2x4 dots, with one color per character cell.
+struct BrailleCanvas {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+    colors: Vec<Option<Color>>,
+}
+impl BrailleCanvas {
+    fn new(width: usize, height: usize) -> Self {
+        BrailleCanvas {
+            width,
+            height,
+            cells: vec![0; width * height],
+            colors: vec![None; width * height],
+        }
+    }
+
+    fn dot_width(&self) -> usize {
+        self.width * 2
+    }
+
+    fn dot_height(&self) -> usize {
+        self.height * 4
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Option<Color>) {
+        if x >= self.dot_width() || y >= self.dot_height() {
+            return;
+        }
+
+        // Bit layout of the braille block, see U+2800
+        const DOT_BITS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        let idx = (y / 4) * self.width + x / 2;
+        self.cells[idx] |= DOT_BITS[y % 4][x % 2];
+        if color.is_some() {
+            self.colors[idx] = color;
+        }
+    }
+
+    fn char_at(&self, col: usize, row: usize) -> char {
+        char::from_u32(0x2800 + self.cells[row * self.width + col] as u32).unwrap()
+    }
+
+    fn draw(&self, out: &mut impl Write, left: u16, top: u16) -> std::io::Result<()> {
+        for row in 0..self.height {
+            queue!(out, cursor::MoveTo(left, top + row as u16))?;
+            for col in 0..self.width {
+                let color = self.colors[row * self.width + col].unwrap_or(Color::DarkGrey);
+                queue!(
+                    out,
+                    style::SetForegroundColor(color),
+                    style::Print(self.char_at(col, row))
+                )?;
+            }
+        }
+        queue!(out, style::ResetColor)
+    }
+}
+
+// Puts the terminal back the way we found it, even if we bail out with an error.
+struct TerminalGuard;
+impl TerminalGuard {
+    fn enter(out: &mut Stdout) -> Result<Self, Box<dyn Error>> {
+        terminal::enable_raw_mode()?;
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        out.flush()?;
+        Ok(TerminalGuard)
+    }
+}
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = std::io::stdout();
+        let _ = queue!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct PlayerState {
+    timestamp: f64,
+    start_time: f64,
+    end_time: f64,
+    speed_idx: usize,
+    paused: bool,
+    // Index into the sorted player list, if an actor is highlighted
+    highlighted: Option<usize>,
+}
+
+fn to_color((r, g, b): (f64, f64, f64)) -> Color {
+    Color::Rgb {
+        r: (r * 255.0) as u8,
+        g: (g * 255.0) as u8,
+        b: (b * 255.0) as u8,
+    }
+}
+
+fn draw_frame(
+    out: &mut Stdout,
+    source: &OverlaySource,
+    player_ids: &[i64],
+    state: &PlayerState,
+) -> Result<(), Box<dyn Error>> {
+    let (cols, rows) = terminal::size()?;
+    let rows = rows.saturating_sub(STATUS_ROWS).max(1);
+
+    // Braille dots are roughly square, so fit a square arena into the available dots.
+    let side = (cols as usize * 2).min(rows as usize * 4).max(2);
+    let mut canvas = BrailleCanvas::new(side / 2, side / 4);
+    let dot_size = (side - 1) as f64;
+
+    let highlighted_id = state.highlighted.map(|idx| player_ids[idx]);
+    let mut highlighted_name = None;
+
+    for (id, info, (rel_x, rel_y)) in sample_players(source, state.timestamp, source.bounding_box) {
+        let color = to_color(job_color(&info.subtype).unwrap_or((1.0, 1.0, 1.0)));
+        let (x, y) = (
+            (rel_x.clamp(0.0, 1.0) * dot_size) as usize,
+            (rel_y.clamp(0.0, 1.0) * dot_size) as usize,
+        );
+
+        let radius: isize = if Some(id) == highlighted_id {
+            highlighted_name = Some(info.name.as_str());
+            2
+        } else {
+            0
+        };
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                canvas.set(
+                    x.saturating_add_signed(dx),
+                    y.saturating_add_signed(dy),
+                    Some(color),
+                );
+            }
+        }
+    }
+
+    // Arena border
+    for i in 0..canvas.dot_width() {
+        canvas.set(i, 0, None);
+        canvas.set(i, canvas.dot_height() - 1, None);
+    }
+    for i in 0..canvas.dot_height() {
+        canvas.set(0, i, None);
+        canvas.set(canvas.dot_width() - 1, i, None);
+    }
+
+    queue!(out, terminal::Clear(terminal::ClearType::All))?;
+    canvas.draw(out, 0, 0)?;
+
+    queue!(
+        out,
+        cursor::MoveTo(0, rows),
+        style::Print(format!(
+            "{} {} / {}  {}x  {}",
+            if state.paused { "||" } else { "> " },
+            format_fight_time(state.timestamp - state.start_time),
+            format_fight_time(state.end_time - state.start_time),
+            SPEEDS[state.speed_idx],
+            highlighted_name.unwrap_or(""),
+        )),
+        cursor::MoveTo(0, rows + 1),
+        style::Print(
+            "space pause  ,/. step  left/right seek  -/+ speed  tab highlight  home/end  q quit"
+        ),
+    )?;
+    out.flush()?;
+
+    Ok(())
+}
+
+// Returns false once the user asked to quit.
+fn handle_key(key: KeyEvent, state: &mut PlayerState, player_count: usize) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return false,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Char(' ') => state.paused = !state.paused,
+        KeyCode::Char('.') => {
+            state.paused = true;
+            state.timestamp += FRAME_DURATION;
+        }
+        KeyCode::Char(',') => {
+            state.paused = true;
+            state.timestamp -= FRAME_DURATION;
+        }
+        KeyCode::Right => state.timestamp += SEEK_STEP,
+        KeyCode::Left => state.timestamp -= SEEK_STEP,
+        KeyCode::Home => state.timestamp = state.start_time,
+        KeyCode::End => state.timestamp = state.end_time,
+        KeyCode::Char('+') | KeyCode::Char('=') => {
+            state.speed_idx = (state.speed_idx + 1).min(SPEEDS.len() - 1)
+        }
+        KeyCode::Char('-') => state.speed_idx = state.speed_idx.saturating_sub(1),
+        KeyCode::Tab if player_count > 0 => {
+            state.highlighted = match state.highlighted {
+                Some(idx) if idx + 1 < player_count => Some(idx + 1),
+                Some(_) => None,
+                None => Some(0),
+            }
+        }
+        _ => {}
+    }
+
+    state.timestamp = state.timestamp.clamp(state.start_time, state.end_time);
+    true
+}
+
+/// Plays a pull back in the terminal until the user quits.
+pub fn play(pull: &Pull, interpolation: Interpolation) -> Result<(), Box<dyn Error>> {
+    let source = OverlaySource::from_pull(pull, interpolation);
+    let (start_time, end_time) = overlay_time_range(std::slice::from_ref(&source));
+
+    let mut player_ids = sample_players(&source, start_time, source.bounding_box)
+        .into_iter()
+        .map(|(id, _, _)| id)
+        .collect::<Vec<_>>();
+    player_ids.sort();
+
+    let mut state = PlayerState {
+        timestamp: start_time,
+        start_time,
+        end_time,
+        speed_idx: SPEEDS.iter().position(|speed| *speed == 1.0).unwrap(),
+        paused: false,
+        highlighted: None,
+    };
+
+    let mut out = std::io::stdout();
+    let _guard = TerminalGuard::enter(&mut out)?;
+
+    let mut next_frame = Instant::now();
+    loop {
+        draw_frame(&mut out, &source, &player_ids, &state)?;
+
+        // Same frame spacing as the video renderer, stretched or squeezed by the playback speed.
+        next_frame += Duration::from_secs_f64(FRAME_DURATION / 1000.0 / SPEEDS[state.speed_idx]);
+        let now = Instant::now();
+        if next_frame < now {
+            next_frame = now;
+        }
+
+        let mut redraw = false;
+        while !redraw {
+            let timeout = next_frame.saturating_duration_since(Instant::now());
+            if event::poll(if state.paused {
+                Duration::from_secs(1)
+            } else {
+                timeout
+            })? {
+                match event::read()? {
+                    TermEvent::Key(key) => {
+                        if !handle_key(key, &mut state, player_ids.len()) {
+                            return Ok(());
+                        }
+                        redraw = true;
+                    }
+                    TermEvent::Resize(_, _) => redraw = true,
+                    _ => {}
+                }
+            } else if !state.paused {
+                state.timestamp = (state.timestamp + FRAME_DURATION).min(end_time);
+                if state.timestamp >= end_time {
+                    state.paused = true;
+                }
+                redraw = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BrailleCanvas;
+
+    #[test]
+    fn braille_canvas_test() {
+        let mut canvas = BrailleCanvas::new(2, 1);
+        assert_eq!(canvas.char_at(0, 0), '\u{2800}');
+
+        canvas.set(0, 0, None);
+        canvas.set(1, 3, None);
+        assert_eq!(canvas.char_at(0, 0), '\u{2881}');
+
+        // Second cell, bottom left dot
+        canvas.set(2, 3, None);
+        assert_eq!(canvas.char_at(1, 0), '\u{2840}');
+
+        // Out of bounds is ignored
+        canvas.set(4, 0, None);
+        canvas.set(0, 4, None);
+        assert_eq!(canvas.cells, vec![0x81, 0x40]);
+    }
+}
@@ -1,32 +1,56 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, error::Error, path::Path};
 
-use cairo::{Context, Format, ImageSurface};
+use cairo::{Context, Format, ImageSurface, PdfSurface, SvgSurface};
 
 use crate::{
-    positions::{Position, PositionHistory, Rect},
+    casts::{boss_casts, BossCast},
+    deaths::DeathRecap,
+    encode::create_encoder,
+    hits::{damage_taken, DamageHit},
+    positions::{Interpolation, Position, PositionHistory, Rect},
+    report::{format_fight_time, Pull},
     ActorInfo,
 };
 
 // frames per second of the fight
 const POSITION_SAMPLE_RATE: f64 = 4.0;
 
+// milliseconds of fight time between two frames
+pub const FRAME_DURATION: f64 = 1000.0 / POSITION_SAMPLE_RATE;
+
 // fps out output video
 const OUTPUT_FRAMERATE: u32 = 30;
 
-fn draw_actor_on_frame(ctx: &Context, info: &ActorInfo, (rel_x, rel_y): Position, frame_size: f64) {
-    let (r, g, b) = match info.subtype.as_str() {
-        "WhiteMage" | "Scholar" | "Sage" | "Astrologian" => (0.247, 0.890, 0.133),
-        "Gunbreaker" | "DarkKnight" | "Warrior" | "Paladin" => (0.137, 0.137, 0.980),
-        "Reaper" | "Samurai" | "Ninja" | "Monk" | "Dragoon" => (0.490, 0.027, 0.027),
-        "Bard" | "Dancer" | "Machinist" => (0.890, 0.259, 0.259),
-        "RedMage" | "BlackMage" | "Summoner" => (0.89, 0.020, 0.020),
-        other => {
-            println!("unknown class: {}", other);
-            (1.0, 1.0, 1.0)
-        },
-    };
+// Finished casts listed under the cast bar
+const CAST_HISTORY: usize = 4;
+
+// How long a hit marker stays up, in milliseconds
+const HIT_MARKER_DURATION: f64 = 1500.0;
+
+pub fn job_color(subtype: &str) -> Option<(f64, f64, f64)> {
+    match subtype {
+        "WhiteMage" | "Scholar" | "Sage" | "Astrologian" => Some((0.247, 0.890, 0.133)),
+        "Gunbreaker" | "DarkKnight" | "Warrior" | "Paladin" => Some((0.137, 0.137, 0.980)),
+        "Reaper" | "Samurai" | "Ninja" | "Monk" | "Dragoon" => Some((0.490, 0.027, 0.027)),
+        "Bard" | "Dancer" | "Machinist" => Some((0.890, 0.259, 0.259)),
+        "RedMage" | "BlackMage" | "Summoner" => Some((0.89, 0.020, 0.020)),
+        _ => None,
+    }
+}
+
+fn draw_actor_on_frame(
+    ctx: &Context,
+    info: &ActorInfo,
+    (rel_x, rel_y): Position,
+    frame_size: f64,
+    tint: Option<(f64, f64, f64)>,
+    confident: bool,
+) {
+    let (r, g, b) = job_color(&info.subtype).unwrap_or_else(|| {
+        println!("unknown class: {}", info.subtype);
+        (1.0, 1.0, 1.0)
+    });
 
-    ctx.set_source_rgb(r, g, b);
     ctx.arc(
         frame_size * rel_x,
         frame_size * rel_y,
@@ -34,84 +58,654 @@ fn draw_actor_on_frame(ctx: &Context, info: &ActorInfo, (rel_x, rel_y): Position
         0.0,
         std::f64::consts::TAU,
     );
-    ctx.fill().unwrap();
+    if confident {
+        ctx.set_source_rgb(r, g, b);
+        ctx.fill().unwrap();
+    } else {
+        // A guess between samples far apart: faded, with just the outline solid
+        ctx.set_source_rgba(r, g, b, 0.3);
+        ctx.fill_preserve().unwrap();
+        ctx.set_source_rgb(r, g, b);
+        ctx.set_line_width(1.0);
+        ctx.stroke().unwrap();
+    }
+
+    if let Some((r, g, b)) = tint {
+        ctx.set_source_rgb(r, g, b);
+        ctx.set_line_width(2.0);
+        ctx.arc(
+            frame_size * rel_x,
+            frame_size * rel_y,
+            7.0,
+            0.0,
+            std::f64::consts::TAU,
+        );
+        ctx.stroke().unwrap();
+    }
+}
+
+/// One set of position histories to draw on the shared arena, shifted so that `time_offset` lines up
+/// with time zero of the overlay.
+pub struct OverlaySource<'a> {
+    pub history: &'a HashMap<i64, PositionHistory>,
+    pub actors: &'a HashMap<i64, ActorInfo>,
+    pub time_offset: f64,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub bounding_box: Rect,
+    // Outline color and legend text used to tell sources apart; unset for single-fight renders.
+    pub tint: Option<(f64, f64, f64)>,
+    pub label: String,
+    // Enemy casts, shown as a cast bar when the source is rendered on its own
+    pub casts: Vec<BossCast>,
+    // Damage players took from enemies, flashed where they got hit
+    pub hits: Vec<DamageHit>,
+    // How to fill in positions between samples
+    pub interpolation: Interpolation,
+}
+
+impl<'a> OverlaySource<'a> {
+    /// A single pull on its own, with overlay time zero at the start of the pull.
+    pub fn from_pull(pull: &'a Pull, interpolation: Interpolation) -> Self {
+        OverlaySource {
+            history: &pull.positions,
+            actors: &pull.actors,
+            time_offset: pull.fight.start_time,
+            start_time: pull.fight.start_time,
+            end_time: pull.fight.end_time,
+            bounding_box: pull.fight.bounding_box,
+            tint: None,
+            label: pull.label(),
+            casts: boss_casts(pull),
+            hits: damage_taken(pull),
+            interpolation,
+        }
+    }
 }
 
-fn concat_images_to_video(dir: impl AsRef<Path>, out_vid: impl AsRef<Path>, target_framerate: u32) {
-    let mut cmd = std::process::Command::new("ffmpeg");
-    cmd.current_dir(dir.as_ref());
-    cmd.arg("-y");
-    cmd.arg("-f").arg("image2");
-    cmd.arg("-r").arg(target_framerate.to_string());
-    cmd.arg("-i").arg("%04d.png");
-    cmd.arg("-c:v").arg("libx264");
-    cmd.arg("-pix_fmt").arg("yuv420p");
-    cmd.arg(out_vid.as_ref());
+// Distinct outline colors handed out to overlaid pulls in order.
+pub const PULL_TINTS: [(f64, f64, f64); 8] = [
+    (1.0, 0.843, 0.0),
+    (0.0, 0.808, 0.820),
+    (1.0, 0.412, 0.706),
+    (0.604, 0.804, 0.196),
+    (1.0, 0.549, 0.0),
+    (0.576, 0.439, 0.859),
+    (0.941, 0.902, 0.549),
+    (0.690, 0.769, 0.871),
+];
 
-    let status = cmd.status().unwrap();
-    assert!(status.success());
+fn union_rect(rects: impl Iterator<Item = Rect>) -> Rect {
+    rects
+        .reduce(
+            |((a_min_x, a_min_y), (a_max_x, a_max_y)), ((b_min_x, b_min_y), (b_max_x, b_max_y))| {
+                (
+                    (a_min_x.min(b_min_x), a_min_y.min(b_min_y)),
+                    (a_max_x.max(b_max_x), a_max_y.max(b_max_y)),
+                )
+            },
+        )
+        .unwrap_or(((0.0, 0.0), (1.0, 1.0)))
+}
+
+fn draw_legend(ctx: &Context, sources: &[OverlaySource]) {
+    ctx.set_font_size(14.0);
+
+    let mut y = 20.0;
+    for source in sources {
+        if let Some((r, g, b)) = source.tint {
+            ctx.set_source_rgb(r, g, b);
+            ctx.rectangle(10.0, y - 10.0, 10.0, 10.0);
+            ctx.fill().unwrap();
+
+            ctx.move_to(26.0, y);
+            ctx.show_text(&source.label).unwrap();
+
+            y += 18.0;
+        }
+    }
 }
 
 pub fn render_animations(
-    history: &HashMap<i64, PositionHistory>,
-    actors: &HashMap<i64, ActorInfo>,
-    start_time: f64,
-    end_time: f64,
-    ((min_x, min_y), (max_x, max_y)): Rect,
-    frame_size: u32,
-    base_output_dir: impl AsRef<Path>,
+    pull: &Pull,
+    interpolation: Interpolation,
+    frame_size: (u32, u32),
+    output: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    render_overlay(
+        &[OverlaySource::from_pull(pull, interpolation)],
+        frame_size,
+        output,
+    )
+}
+
+// The enemies' current casts as bars across the top of the frame, with the last few finished casts
+// listed underneath.
+fn draw_cast_bars(ctx: &Context, source: &OverlaySource, timestamp: f64, width: f64) {
+    let time = timestamp + source.time_offset;
+    let bar_width = (width * 0.5).min(400.0);
+    let left = (width - bar_width) / 2.0;
+    let mut y = 10.0;
+
+    ctx.set_font_size(14.0);
+    for cast in &source.casts {
+        let Some(progress) = cast.progress(time) else {
+            continue;
+        };
+
+        ctx.set_source_rgb(0.2, 0.2, 0.2);
+        ctx.rectangle(left, y, bar_width, 18.0);
+        ctx.fill().unwrap();
+        ctx.set_source_rgb(0.85, 0.55, 0.1);
+        ctx.rectangle(left, y, bar_width * progress, 18.0);
+        ctx.fill().unwrap();
+
+        ctx.set_source_rgb(1.0, 1.0, 1.0);
+        ctx.move_to(left + 6.0, y + 14.0);
+        ctx.show_text(&cast.name).unwrap();
+
+        let remaining = format!("{:.1}s", (cast.end - time) / 1000.0);
+        let extents = ctx.text_extents(&remaining).unwrap();
+        ctx.move_to(left + bar_width - extents.width() - 6.0, y + 14.0);
+        ctx.show_text(&remaining).unwrap();
+
+        y += 24.0;
+    }
+
+    let mut finished = source
+        .casts
+        .iter()
+        .filter(|cast| cast.end <= time)
+        .collect::<Vec<_>>();
+    finished.sort_by(|a, b| b.end.total_cmp(&a.end));
+
+    ctx.set_font_size(12.0);
+    for (i, cast) in finished.iter().take(CAST_HISTORY).enumerate() {
+        // Older casts fade out
+        let alpha = 0.9 - i as f64 * 0.15;
+        ctx.set_source_rgba(0.8, 0.8, 0.8, alpha);
+        ctx.move_to(left, y + 12.0);
+        ctx.show_text(&format!(
+            "{}  {}{}",
+            format_fight_time(cast.end - source.time_offset),
+            cast.name,
+            if cast.completed { "" } else { " (interrupted)" }
+        ))
+        .unwrap();
+        y += 15.0;
+    }
+}
+
+// Centers the largest square that fits into a `width` x `height` frame and returns its side, so
+// the arena keeps its shape in non-square videos.
+fn letterbox(ctx: &Context, (width, height): (f64, f64)) -> f64 {
+    let side = width.min(height);
+    ctx.translate((width - side) / 2.0, (height - side) / 2.0);
+    side
+}
+
+/// Draws every source onto the same arena. Each source is only drawn while its own fight is in
+/// progress, so pulls of different lengths simply drop out of the video once they end.
+pub fn render_overlay(
+    sources: &[OverlaySource],
+    (width, height): (u32, u32),
+    output: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let bounding_box = union_rect(sources.iter().map(|source| source.bounding_box));
+
+    render_frames(
+        sources,
+        (width as i32, height as i32),
+        output,
+        |ctx, timestamp| {
+            ctx.save().unwrap();
+            let side = letterbox(ctx, (width as f64, height as f64));
+            for source in sources {
+                draw_source(ctx, source, timestamp, bounding_box, side);
+            }
+            ctx.restore().unwrap();
+
+            // Several pulls' casts would just pile on top of each other
+            if let [source] = sources {
+                draw_cast_bars(ctx, source, timestamp, width as f64);
+            }
+            draw_legend(ctx, sources);
+        },
+    )
+}
+
+/// Draws each source in its own cell of a `columns`-wide grid, every cell using its own arena and
+/// captioned with the source's label. The video is exactly as big as the grid unless `frame_size`
+/// is given, in which case the cells shrink or grow to fit it and the grid is centered.
+pub fn render_grid(
+    sources: &[OverlaySource],
+    columns: usize,
+    cell_size: u32,
+    frame_size: Option<(u32, u32)>,
+    output: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let columns = columns.clamp(1, sources.len().max(1));
+    let rows = sources.len().div_ceil(columns).max(1);
+
+    let (width, height, cell_size) = match frame_size {
+        Some((width, height)) => (
+            width,
+            height,
+            (width / columns as u32).min(height / rows as u32) as f64,
+        ),
+        None => (
+            columns as u32 * cell_size,
+            rows as u32 * cell_size,
+            cell_size as f64,
+        ),
+    };
+    if cell_size < 1.0 {
+        return Err(format!(
+            "{}x{} is too small for a {}x{} grid",
+            width, height, columns, rows
+        )
+        .into());
+    }
+    let grid_offset = (
+        ((width as f64 - columns as f64 * cell_size) / 2.0).floor(),
+        ((height as f64 - rows as f64 * cell_size) / 2.0).floor(),
+    );
+
+    render_frames(
+        sources,
+        (width as i32, height as i32),
+        output,
+        |ctx, timestamp| {
+            ctx.translate(grid_offset.0, grid_offset.1);
+            for (i, source) in sources.iter().enumerate() {
+                ctx.save().unwrap();
+                ctx.translate(
+                    (i % columns) as f64 * cell_size,
+                    (i / columns) as f64 * cell_size,
+                );
+                ctx.rectangle(0.0, 0.0, cell_size, cell_size);
+                ctx.clip();
+
+                draw_source(ctx, source, timestamp, source.bounding_box, cell_size);
+
+                // Cell border and caption
+                ctx.set_source_rgb(0.3, 0.3, 0.3);
+                ctx.set_line_width(1.0);
+                ctx.rectangle(0.5, 0.5, cell_size - 1.0, cell_size - 1.0);
+                ctx.stroke().unwrap();
+
+                ctx.set_source_rgb(1.0, 1.0, 1.0);
+                ctx.set_font_size(14.0);
+                ctx.move_to(8.0, cell_size - 8.0);
+                ctx.show_text(&source.label).unwrap();
+
+                ctx.restore().unwrap();
+            }
+        },
+    )
+}
+
+fn draw_source(
+    ctx: &Context,
+    source: &OverlaySource,
+    timestamp: f64,
+    bounding_box: Rect,
+    frame_size: f64,
 ) {
-    std::fs::create_dir_all(base_output_dir.as_ref()).unwrap();
+    let source_time = timestamp + source.time_offset;
+    for (id, info, position) in sample_players(source, timestamp, bounding_box) {
+        let confident = source.history[&id].is_confident_at(source_time);
+        draw_actor_on_frame(ctx, info, position, frame_size, source.tint, confident);
+    }
+    draw_hits(ctx, source, timestamp, bounding_box, frame_size);
+}
 
-    let render_start_time = std::time::Instant::now();
+// A ring that grows and fades where each recent hit landed, labelled with the ability and damage.
+// Hits that killed are drawn in red.
+fn draw_hits(
+    ctx: &Context,
+    source: &OverlaySource,
+    timestamp: f64,
+    ((min_x, min_y), (max_x, max_y)): Rect,
+    frame_size: f64,
+) {
+    let time = timestamp + source.time_offset;
 
-    let frame_size = frame_size as i32;
-    let frame_duration: f64 = 1000.0 / POSITION_SAMPLE_RATE;
+    ctx.set_font_size(11.0);
+    ctx.set_line_width(2.0);
+    for hit in &source.hits {
+        let age = time - hit.timestamp;
+        if !(0.0..HIT_MARKER_DURATION).contains(&age) {
+            continue;
+        }
+        let Some(history) = source.history.get(&hit.target).filter(|h| !h.is_empty()) else {
+            continue;
+        };
 
-    let arena_width = max_x - min_x;
-    let arena_height = max_y - min_y;
+        let (x, y) = history.position_at(hit.timestamp, source.interpolation);
+        let x = (x - min_x) / (max_x - min_x) * frame_size;
+        let y = (y - min_y) / (max_y - min_y) * frame_size;
+        let fade = 1.0 - age / HIT_MARKER_DURATION;
+        let (r, g, b) = if hit.fatal {
+            (1.0, 0.1, 0.1)
+        } else {
+            (1.0, 0.8, 0.2)
+        };
 
-    let mut timestamp = start_time;
-    let mut frame_idx = 0;
+        ctx.set_source_rgba(r, g, b, fade);
+        ctx.new_path();
+        ctx.arc(x, y, 9.0 + 12.0 * (1.0 - fade), 0.0, std::f64::consts::TAU);
+        ctx.stroke().unwrap();
 
-    while timestamp < end_time {
-        let frame_filename = {
-            let mut p = base_output_dir.as_ref().to_path_buf();
-            p.push(format!("{:04}.png", frame_idx));
-            p
+        let text = if hit.overkill > 0 {
+            format!("{} {} ({} overkill)", hit.name, hit.amount, hit.overkill)
+        } else if hit.fatal {
+            format!("{} {} (fatal)", hit.name, hit.amount)
+        } else {
+            format!("{} {}", hit.name, hit.amount)
         };
+        ctx.move_to(x + 12.0, y - 10.0);
+        ctx.show_text(&text).unwrap();
+    }
+}
+
+// Each player's path over the `trail` milliseconds leading up to `timestamp`, fading in towards the
+// present.
+fn draw_trails(
+    ctx: &Context,
+    source: &OverlaySource,
+    timestamp: f64,
+    trail: f64,
+    bounding_box: Rect,
+    frame_size: f64,
+) {
+    const TRAIL_STEP: f64 = 100.0;
+
+    // The trail doesn't reach back past the start of the pull
+    let end = timestamp + source.time_offset;
+    let start = (end - trail).max(source.start_time);
+    let steps = ((end - start) / TRAIL_STEP).ceil() as usize;
+    if steps == 0 {
+        return;
+    }
+    let step = (end - start) / steps as f64;
+
+    let ((min_x, min_y), (max_x, max_y)) = bounding_box;
+    let to_frame = |(x, y): Position| {
+        (
+            (x - min_x) / (max_x - min_x) * frame_size,
+            (y - min_y) / (max_y - min_y) * frame_size,
+        )
+    };
+
+    let mut track = vec![(0.0, 0.0); steps + 1];
+    ctx.set_line_width(2.0);
+    for (id, info, _) in sample_players(source, timestamp, bounding_box) {
+        source.history[&id].resample_into(start, step, source.interpolation, &mut track);
+
+        let (r, g, b) = job_color(&info.subtype).unwrap_or((1.0, 1.0, 1.0));
+        for (i, pair) in track.windows(2).enumerate() {
+            let alpha = 0.1 + 0.6 * (i + 1) as f64 / steps as f64;
+            let ((x0, y0), (x1, y1)) = (to_frame(pair[0]), to_frame(pair[1]));
+            ctx.set_source_rgba(r, g, b, alpha);
+            ctx.move_to(x0, y0);
+            ctx.line_to(x1, y1);
+            ctx.stroke().unwrap();
+        }
+    }
+}
 
-        let image_surface = ImageSurface::create(Format::Rgb24, frame_size, frame_size).unwrap();
-        let ctx = Context::new(&image_surface).unwrap();
+fn draw_snapshot(
+    ctx: &Context,
+    source: &OverlaySource,
+    timestamp: f64,
+    trail: f64,
+    frame_size: f64,
+) {
+    ctx.set_source_rgb(0.0, 0.0, 0.0);
+    ctx.paint().unwrap();
+
+    draw_trails(
+        ctx,
+        source,
+        timestamp,
+        trail,
+        source.bounding_box,
+        frame_size,
+    );
+    draw_source(ctx, source, timestamp, source.bounding_box, frame_size);
 
-        ctx.scale(1.0, 1.0);
+    ctx.set_source_rgb(1.0, 1.0, 1.0);
+    ctx.set_font_size(14.0);
+    ctx.move_to(10.0, frame_size - 10.0);
+    ctx.show_text(&format!(
+        "{}  {}",
+        source.label,
+        format_fight_time(timestamp + source.time_offset - source.start_time)
+    ))
+    .unwrap();
+}
+
+/// A PNG of the arena as `recap`'s player died, with everyone's trails over the preceding `trail`
+/// milliseconds and the player's own path and final position picked out in white.
+pub fn render_death_snapshot(
+    source: &OverlaySource,
+    recap: &DeathRecap,
+    trail: f64,
+    frame_size: u32,
+    output: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let size = frame_size as f64;
+    let surface = ImageSurface::create(Format::Rgb24, frame_size as i32, frame_size as i32)?;
+    let ctx = Context::new(&surface)?;
+    draw_snapshot(
+        &ctx,
+        source,
+        recap.timestamp - source.time_offset,
+        trail,
+        size,
+    );
 
-        for (id, history) in history {
-            let info = actors.get(id).unwrap();
-            match info.type_.as_str() {
-                "Player" => {
-                    let position = history.get_position_at(timestamp);
+    let ((min_x, min_y), (max_x, max_y)) = source.bounding_box;
+    let points = recap
+        .trail
+        .iter()
+        .map(|(x, y)| {
+            (
+                (x - min_x) / (max_x - min_x) * size,
+                (y - min_y) / (max_y - min_y) * size,
+            )
+        })
+        .collect::<Vec<_>>();
+    if let Some((&(x, y), rest)) = points.split_last() {
+        ctx.set_source_rgb(1.0, 1.0, 1.0);
+        ctx.set_line_width(3.0);
+        ctx.new_path();
+        for &(px, py) in rest {
+            ctx.line_to(px, py);
+        }
+        ctx.line_to(x, y);
+        ctx.stroke()?;
+
+        ctx.move_to(x - 8.0, y - 8.0);
+        ctx.line_to(x + 8.0, y + 8.0);
+        ctx.move_to(x + 8.0, y - 8.0);
+        ctx.line_to(x - 8.0, y + 8.0);
+        ctx.stroke()?;
+    }
+
+    surface.write_to_png(&mut std::io::BufWriter::new(std::fs::File::create(
+        output.as_ref(),
+    )?))?;
+    Ok(())
+}
+
+/// Renders still images of `source` at each of the given overlay times, with optional trails of
+/// the preceding `trail` milliseconds. PDF output puts every timestamp on its own page of a single
+/// file; SVG and PNG output write one file per timestamp, suffixed with the time when there's more
+/// than one.
+pub fn render_snapshots(
+    source: &OverlaySource,
+    timestamps: &[f64],
+    trail: f64,
+    frame_size: u32,
+    output: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let output = output.as_ref();
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
 
-                    let rel_x = (position.0 - min_x) / arena_width;
-                    let rel_y = (position.1 - min_y) / arena_height;
-                    draw_actor_on_frame(&ctx, info, (rel_x, rel_y), frame_size as f64);
-                }
+    let extension = output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let size = frame_size as f64;
 
-                _ => {}
+    if extension.as_deref() == Some("pdf") {
+        let surface = PdfSurface::new(size, size, output)?;
+        let ctx = Context::new(&surface)?;
+        for timestamp in timestamps {
+            draw_snapshot(&ctx, source, *timestamp, trail, size);
+            ctx.show_page()?;
+        }
+        surface.finish();
+        println!("Wrote {}", output.display());
+        return Ok(());
+    }
+
+    for timestamp in timestamps {
+        let path = if timestamps.len() > 1 {
+            let time = format_fight_time(timestamp + source.time_offset - source.start_time)
+                .replace(':', "-");
+            output.with_file_name(format!(
+                "{}_{}.{}",
+                output.file_stem().unwrap_or_default().to_string_lossy(),
+                time,
+                extension.as_deref().unwrap_or_default()
+            ))
+        } else {
+            output.to_path_buf()
+        };
+
+        match extension.as_deref() {
+            Some("svg") => {
+                let surface = SvgSurface::new(size, size, Some(&path))?;
+                draw_snapshot(&Context::new(&surface)?, source, *timestamp, trail, size);
+                surface.finish();
+            }
+            Some("png") => {
+                let surface =
+                    ImageSurface::create(Format::Rgb24, frame_size as i32, frame_size as i32)?;
+                draw_snapshot(&Context::new(&surface)?, source, *timestamp, trail, size);
+                surface
+                    .write_to_png(&mut std::io::BufWriter::new(std::fs::File::create(&path)?))?;
+            }
+            _ => {
+                return Err(format!(
+                    "can't tell the snapshot format of {}; use .svg, .pdf or .png",
+                    output.display()
+                )
+                .into())
             }
         }
 
-        let mut f = std::io::BufWriter::new(std::fs::File::create(frame_filename).unwrap());
-        image_surface.write_to_png(&mut f).unwrap();
+        println!("Wrote {}", path.display());
+    }
 
-        timestamp += frame_duration;
-        frame_idx += 1;
+    Ok(())
+}
 
-        if frame_idx % 10 == 0 {
-            println!("Rendered frame {}", frame_idx);
+/// Positions of every player in `source` at the given overlay time, scaled so that the bounding box
+/// spans 0..1 on both axes. Empty when the source's fight isn't in progress at that time.
+pub fn sample_players<'a>(
+    source: &OverlaySource<'a>,
+    timestamp: f64,
+    ((min_x, min_y), (max_x, max_y)): Rect,
+) -> Vec<(i64, &'a ActorInfo, Position)> {
+    let source_time = timestamp + source.time_offset;
+    if source_time < source.start_time || source_time > source.end_time {
+        return Vec::new();
+    }
+
+    let arena_width = max_x - min_x;
+    let arena_height = max_y - min_y;
+
+    // Positions can outlive what the log says about an actor; those aren't anyone to draw
+    source
+        .history
+        .iter()
+        .filter_map(|(id, history)| Some((id, source.actors.get(id)?, history)))
+        .filter(|(_, info, history)| info.type_ == "Player" && !history.is_empty())
+        .map(|(id, info, history)| {
+            let position = history.position_at(source_time, source.interpolation);
+
+            let rel_x = (position.0 - min_x) / arena_width;
+            let rel_y = (position.1 - min_y) / arena_height;
+            (*id, info, (rel_x, rel_y))
+        })
+        .collect()
+}
+
+/// The overlay time range covered by at least one of the sources.
+pub fn overlay_time_range(sources: &[OverlaySource]) -> (f64, f64) {
+    let start_time = sources
+        .iter()
+        .map(|source| source.start_time - source.time_offset)
+        .fold(f64::INFINITY, f64::min);
+    let end_time = sources
+        .iter()
+        .map(|source| source.end_time - source.time_offset)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    (start_time, end_time)
+}
+
+// Steps through the combined (offset-adjusted) time range of all sources, writing one PNG per step
+// and then stitching them into a video.
+fn render_frames(
+    sources: &[OverlaySource],
+    (width, height): (i32, i32),
+    output: impl AsRef<Path>,
+    draw_frame: impl Fn(&Context, f64),
+) -> Result<(), Box<dyn Error>> {
+    let render_start_time = std::time::Instant::now();
+
+    let (start_time, end_time) = overlay_time_range(sources);
+    let frame_count = ((end_time - start_time) / FRAME_DURATION).ceil().max(0.0) as usize;
+
+    let mut encoder = create_encoder(
+        output.as_ref(),
+        (width, height),
+        frame_count,
+        OUTPUT_FRAMERATE,
+    )?;
+
+    for frame_idx in 0..frame_count {
+        let timestamp = start_time + frame_idx as f64 * FRAME_DURATION;
+
+        let mut image_surface = ImageSurface::create(Format::Rgb24, width, height)?;
+        {
+            let ctx = Context::new(&image_surface)?;
+
+            ctx.scale(1.0, 1.0);
+
+            draw_frame(&ctx, timestamp);
+        }
+
+        encoder.add_frame(&mut image_surface)?;
+
+        if (frame_idx + 1) % 10 == 0 {
+            println!("Rendered frame {}", frame_idx + 1);
         }
     }
 
-    println!("Rendered all frames in {:?}", std::time::Instant::now() - render_start_time);
-    concat_images_to_video(&base_output_dir, "__result.mp4", OUTPUT_FRAMERATE);
+    println!(
+        "Rendered all frames in {:?}",
+        std::time::Instant::now() - render_start_time
+    );
+    encoder.finish()?;
+    println!("Wrote {}", output.as_ref().display());
+
+    Ok(())
 }
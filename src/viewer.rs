@@ -0,0 +1,286 @@
+use std::{collections::BTreeMap, error::Error, path::Path};
+
+use serde::Serialize;
+
+use crate::{events::Event, report::Pull, video::job_color};
+
+const VIEWER_TEMPLATE: &str = include_str!("../templates/viewer.html");
+const DATA_PLACEHOLDER: &str = "/*FIGHT_DATA*/null";
+
+// Everything the HTML player needs, with times in milliseconds relative to the start of the fight.
+// Per-event lists are tuples so they serialize as plain JSON arrays and keep the file small.
+#[derive(Serialize)]
+struct ViewerData<'a> {
+    title: String,
+    duration: f64,
+    // min x, min y, max x, max y
+    bounds: [f64; 4],
+    actors: Vec<ViewerActor<'a>>,
+    // (time, source, target, ability, cast duration); duration is 0 for completed casts
+    casts: Vec<(f64, i64, i64, i64, i64)>,
+    // (time, source, target, ability, duration); duration is -1 for removals
+    statuses: Vec<(f64, i64, i64, i64, i64)>,
+    // (time, kind, source, target, id) where kind is "marker" or "tether"
+    telegraphs: Vec<(f64, &'static str, i64, i64, i64)>,
+    // Every ability referenced above that the report has a name for
+    abilities: BTreeMap<i64, ViewerAbility<'a>>,
+}
+
+#[derive(Serialize)]
+struct ViewerAbility<'a> {
+    name: &'a str,
+    icon: Option<String>,
+    #[serde(rename = "type")]
+    type_: &'a str,
+}
+
+#[derive(Serialize)]
+struct ViewerActor<'a> {
+    id: i64,
+    name: &'a str,
+    #[serde(rename = "type")]
+    type_: &'a str,
+    job: &'a str,
+    color: String,
+    // Flattened (time, x, y, facing in radians, hp, max hp) samples, in time order
+    track: Vec<f64>,
+}
+
+fn build_viewer_data(pull: &Pull) -> ViewerData<'_> {
+    let start = pull.fight.start_time;
+    let rel = |timestamp: i64| timestamp as f64 - start;
+
+    let mut actors = pull
+        .actors
+        .iter()
+        .filter(|(id, _)| pull.positions.contains_key(id))
+        .map(|(id, info)| {
+            let (r, g, b) = job_color(&info.subtype).unwrap_or(if info.type_ == "Player" {
+                (1.0, 1.0, 1.0)
+            } else {
+                (0.6, 0.6, 0.6)
+            });
+
+            ViewerActor {
+                id: *id,
+                name: &info.name,
+                type_: &info.type_,
+                job: &info.subtype,
+                color: format!(
+                    "#{:02x}{:02x}{:02x}",
+                    (r * 255.0) as u8,
+                    (g * 255.0) as u8,
+                    (b * 255.0) as u8
+                ),
+                track: Vec::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+    actors.sort_by_key(|actor| actor.id);
+
+    let mut casts = Vec::new();
+    let mut statuses = Vec::new();
+    let mut telegraphs = Vec::new();
+
+    for event in &pull.events {
+        let time = rel(event.get_timestamp());
+
+        for (id, res) in [event.get_source_resources(), event.get_target_resources()]
+            .into_iter()
+            .flatten()
+        {
+            if let Ok(idx) = actors.binary_search_by_key(&id, |actor| actor.id) {
+                actors[idx].track.extend([
+                    time,
+                    res.x as f64,
+                    res.y as f64,
+                    (res.facing_radians() * 100.0).round() / 100.0,
+                    res.hit_points as f64,
+                    res.max_hit_points as f64,
+                ]);
+            }
+        }
+
+        match event {
+            Event::BeginCast {
+                ability_game_id,
+                duration,
+                source,
+                target,
+                ..
+            } => casts.push((time, source.id, target.id, *ability_game_id, *duration)),
+            Event::Cast {
+                ability_game_id,
+                source,
+                target,
+                ..
+            } => casts.push((time, source.id, target.id, *ability_game_id, 0)),
+
+            Event::ApplyBuff {
+                ability_game_id,
+                duration,
+                source,
+                target,
+                ..
+            }
+            | Event::ApplyDebuff {
+                ability_game_id,
+                duration,
+                source,
+                target,
+                ..
+            }
+            | Event::RefreshBuff {
+                ability_game_id,
+                duration,
+                source,
+                target,
+                ..
+            }
+            | Event::RefreshDebuff {
+                ability_game_id,
+                duration,
+                source,
+                target,
+                ..
+            } => statuses.push((time, source.id, target.id, *ability_game_id, *duration)),
+            Event::RemoveBuff {
+                ability_game_id,
+                source,
+                target,
+                ..
+            }
+            | Event::RemoveDebuff {
+                ability_game_id,
+                source,
+                target,
+                ..
+            } => statuses.push((time, source.id, target.id, *ability_game_id, -1)),
+
+            Event::HeadMarker { source, target, .. } => {
+                telegraphs.push((time, "marker", source.id, target.id, 0))
+            }
+            Event::Tether {
+                ability_game_id,
+                source,
+                target,
+                ..
+            } => telegraphs.push((time, "tether", source.id, target.id, *ability_game_id)),
+
+            _ => {}
+        }
+    }
+
+    let ability_ids = casts
+        .iter()
+        .map(|cast| cast.3)
+        .chain(statuses.iter().map(|status| status.3))
+        .chain(telegraphs.iter().map(|telegraph| telegraph.4));
+    let mut abilities = BTreeMap::new();
+    for id in ability_ids {
+        if let Some(info) = pull.abilities.get(id) {
+            abilities.entry(id).or_insert_with(|| ViewerAbility {
+                name: &info.name,
+                icon: pull.abilities.icon_url(id),
+                type_: &info.type_,
+            });
+        }
+    }
+
+    let ((min_x, min_y), (max_x, max_y)) = pull.fight.bounding_box;
+
+    ViewerData {
+        title: format!("{} ({})", pull.label(), pull.fight.outcome_text()),
+        duration: pull.fight.end_time - start,
+        bounds: [min_x, min_y, max_x, max_y],
+        actors,
+        casts,
+        statuses,
+        telegraphs,
+        abilities,
+    }
+}
+
+/// Writes a single HTML file containing the fight data and a canvas player for it. The file can be
+/// opened straight from disk; only the ability icons are loaded from FF Logs.
+pub fn export_viewer(pull: &Pull, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    let html = render_viewer_html(pull)?;
+
+    if let Some(dir) = path.as_ref().parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path.as_ref(), html)?;
+
+    println!("Wrote viewer to {}", path.as_ref().display());
+
+    Ok(())
+}
+
+pub fn render_viewer_html(pull: &Pull) -> Result<String, Box<dyn Error>> {
+    // Keep names like "</script>" in the data from ending the script block early.
+    let data = serde_json::to_string(&build_viewer_data(pull))?.replace("</", "<\\/");
+
+    Ok(VIEWER_TEMPLATE.replace(DATA_PLACEHOLDER, &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::test_util::{actor, pull_with};
+
+    use super::{build_viewer_data, render_viewer_html, DATA_PLACEHOLDER, VIEWER_TEMPLATE};
+
+    #[test]
+    fn viewer_data_test() {
+        let resources = |x| serde_json::json!({"hitPoints": 50000, "maxHitPoints": 100000, "mp": 10000, "x": x, "y": 200, "facing": 500});
+        let mut pull = pull_with(
+            [
+                (1, actor("</script>", "Player", "Paladin")),
+                (100, actor("Hephaistos", "NPC", "Boss")),
+            ],
+            serde_json::json!([
+                {"type": "begincast", "abilityGameID": 31000, "duration": 3000, "sourceID": 100, "targetID": -1, "timestamp": 1000},
+                {"type": "cast", "abilityGameID": 7, "sourceID": 1, "targetID": 100, "timestamp": 2000, "sourceResources": resources(100)},
+                {"type": "applydebuff", "abilityGameID": 1002000, "duration": 10000, "sourceID": 100, "targetID": 1, "timestamp": 2500},
+                {"type": "removedebuff", "abilityGameID": 1002000, "sourceID": 100, "targetID": 1, "timestamp": 4000},
+                {"type": "tether", "abilityGameID": 84, "sourceID": 100, "targetID": 1, "timestamp": 4500},
+                {"type": "cast", "abilityGameID": 7, "sourceID": 1, "targetID": 100, "timestamp": 5000, "sourceResources": resources(300)}
+            ]),
+        );
+        pull.fight.start_time = 500.0;
+
+        let data = build_viewer_data(&pull);
+        assert_eq!(data.duration, 9500.0);
+        // Only actors with positions get a track
+        assert_eq!(data.actors.len(), 1);
+        assert_eq!(data.actors[0].id, 1);
+        assert_eq!(
+            data.actors[0].track,
+            vec![
+                1500.0, 100.0, 200.0, 1.57, 50000.0, 100000.0, //
+                4500.0, 300.0, 200.0, 1.57, 50000.0, 100000.0,
+            ]
+        );
+        assert_eq!(
+            data.casts,
+            vec![
+                (500.0, 100, -1, 31000, 3000),
+                (1500.0, 1, 100, 7, 0),
+                (4500.0, 1, 100, 7, 0)
+            ]
+        );
+        assert_eq!(
+            data.statuses,
+            vec![
+                (2000.0, 100, 1, 1002000, 10000),
+                (3500.0, 100, 1, 1002000, -1)
+            ]
+        );
+        assert_eq!(data.telegraphs, vec![(4000.0, "tether", 100, 1, 84)]);
+
+        assert!(VIEWER_TEMPLATE.contains(DATA_PLACEHOLDER));
+        let html = render_viewer_html(&pull).unwrap();
+        assert!(!html.contains(DATA_PLACEHOLDER));
+        assert!(html.contains(r#""name":"<\/script>""#));
+        assert!(!html.contains(r#""name":"</script>""#));
+    }
+}